@@ -1,3 +1,34 @@
 use std::fmt::Debug;
+use std::hash::Hash;
 
-pub trait CombinatorialClass: Debug + Clone + PartialEq {}
+/// A combinatorial class. Classes are hashed so that the class database finds the
+/// label of a class in constant time.
+pub trait CombinatorialClass: Debug + Clone + Eq + Hash {
+    /// Return a codec for a compact encoding of the classes, or `None` if the class
+    /// database should store the classes as they are.
+    fn codec() -> Option<Box<dyn ClassCodec<Self>>> {
+        None
+    }
+
+    /// Return whether the class is empty, or `None` if it is not known without
+    /// searching.
+    fn is_empty(&self) -> Option<bool> {
+        None
+    }
+}
+
+/// Compact encoding of the classes of a class storage.
+///
+/// The codec lives as long as the storage, so what many classes have in common can
+/// be kept once by the codec instead of in the encoding of each class.
+pub trait ClassCodec<C> {
+    /// Keep what the codec needs to encode the class.
+    fn intern(&mut self, class: &C);
+
+    /// Return the encoding of the class, or `None` if it can not be encoded with
+    /// what the codec kept so far. Equal classes must have equal encodings.
+    fn encode(&self, class: &C) -> Option<Vec<u8>>;
+
+    /// Decode a class encoded by `encode`.
+    fn decode(&self, bytes: &[u8]) -> C;
+}
@@ -1,2 +1,28 @@
 #[derive(Debug)]
 pub struct SpecificationNotFoundError {}
+
+#[derive(Debug)]
+pub struct PathNotFoundError {}
+
+#[derive(Debug)]
+pub struct InvalidMinimizeOrderError {}
+
+/// Error of a search, either because there is no specification yet or because
+/// the classes could not be stored or read.
+#[derive(Debug)]
+pub enum SearchError {
+    SpecificationNotFound(SpecificationNotFoundError),
+    Io(std::io::Error),
+}
+
+impl From<SpecificationNotFoundError> for SearchError {
+    fn from(error: SpecificationNotFoundError) -> Self {
+        SearchError::SpecificationNotFound(error)
+    }
+}
+
+impl From<std::io::Error> for SearchError {
+    fn from(error: std::io::Error) -> Self {
+        SearchError::Io(error)
+    }
+}
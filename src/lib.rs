@@ -6,5 +6,8 @@ mod specification;
 pub mod word;
 
 pub use searcher::ruledb;
-pub use searcher::CombinatorialSpecificationSearcher;
+pub use searcher::{
+    ClassInfo, ClassStorage, CombinatorialSpecificationSearcher, DiskStorage, FactorySet,
+    MemoryStorage, DEFAULT_CACHE_CAPACITY,
+};
 pub use specification::CombinatorialSpecification;
@@ -2,7 +2,6 @@ use comb_spec_searcher::pack::StrategyPack;
 use comb_spec_searcher::ruledb;
 use comb_spec_searcher::word;
 use comb_spec_searcher::CombinatorialSpecificationSearcher;
-use serde_json;
 
 fn main() {
     let prefix = String::from("");
@@ -12,7 +11,7 @@ fn main() {
     let pack = StrategyPack {
         initials: vec![word::WordStrategy::RemoveFrontOfPrefix],
         inferrals: vec![],
-        expansions: vec![word::WordStrategy::Expansion],
+        expansions: vec![vec![word::WordStrategy::Expansion]],
         verifications: vec![word::WordStrategy::Empty, word::WordStrategy::Atom],
     };
     let ruledb = ruledb::SimpleRuleDB::new();
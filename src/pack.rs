@@ -6,6 +6,7 @@ pub struct Rule<S: Strategy> {
     parent: S::ClassType,
     strategy: S,
     children: Vec<S::ClassType>,
+    reverse: bool,
 }
 
 impl<S: Strategy> Rule<S> {
@@ -15,9 +16,42 @@ impl<S: Strategy> Rule<S> {
             parent,
             strategy,
             children,
+            reverse: false,
         }
     }
 
+    /// Return the rule that computes the child at the given index from the parent
+    /// and the other children.
+    ///
+    /// The children of the reverse rule are the parent of the rule followed by the
+    /// other children in their original order.
+    pub fn to_reverse_rule(&self, idx: usize) -> Rule<S> {
+        assert!(self.is_reversible(), "The rule is not reversible");
+        assert!(!self.reverse, "The rule is already a reverse rule");
+        let mut children = vec![self.parent.clone()];
+        children.extend(
+            self.children
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != idx)
+                .map(|(_, c)| c.clone()),
+        );
+        Rule {
+            parent: self.children[idx].clone(),
+            strategy: self.strategy.clone(),
+            children,
+            reverse: true,
+        }
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    pub fn is_reversible(&self) -> bool {
+        self.strategy.is_reversible()
+    }
+
     pub fn get_strategy(self) -> S {
         self.strategy
     }
@@ -33,6 +67,15 @@ impl<S: Strategy> Rule<S> {
     pub fn get_children(&self) -> &Vec<S::ClassType> {
         &self.children
     }
+
+    pub fn get_shifts(&self) -> Vec<i32> {
+        if self.reverse {
+            // A reversible rule preserves the size of the objects.
+            vec![0; self.children.len()]
+        } else {
+            self.strategy.shifts(&self.parent)
+        }
+    }
 }
 
 pub trait Strategy: Debug + Sized + Clone {
@@ -40,6 +83,16 @@ pub trait Strategy: Debug + Sized + Clone {
 
     fn decompose(&self, comb_class: &Self::ClassType) -> Vec<Self::ClassType>;
     fn is_equivalence(&self) -> bool;
+
+    /// Return the size shift of each child of the decomposition of the class.
+    ///
+    /// The terms of size n of the class only depend on the terms of size at most
+    /// n - shift of the corresponding child.
+    fn shifts(&self, comb_class: &Self::ClassType) -> Vec<i32>;
+
+    /// Return true if each child can be computed from the parent and the other
+    /// children, for example for an equivalence or a disjoint union.
+    fn is_reversible(&self) -> bool;
 }
 
 pub trait StrategyFactory {
@@ -52,7 +105,9 @@ pub trait StrategyFactory {
 pub struct StrategyPack<F: StrategyFactory> {
     pub initials: Vec<F>,
     pub inferrals: Vec<F>,
-    pub expansions: Vec<F>,
+    /// The tiers of expansion strategies. A tier is only applied to a class once
+    /// every class has been expanded with the earlier tiers.
+    pub expansions: Vec<Vec<F>>,
     pub verifications: Vec<F>,
 }
 
@@ -63,10 +118,14 @@ where
     pub fn len(&self) -> usize {
         self.initials.len()
             + self.inferrals.len()
-            + self.expansions.len()
+            + self.expansions.iter().map(Vec::len).sum::<usize>()
             + self.verifications.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn get_strategy_factory(&self, index: usize) -> &F {
         let mut index = index;
         if index < self.verifications.len() {
@@ -84,11 +143,14 @@ where
         } else {
             index -= self.initials.len();
         }
-        if index < self.expansions.len() {
-            return &self.expansions[index];
-        } else {
-            panic!("Index of strategy out of bound!");
+        for tier in self.expansions.iter() {
+            if index < tier.len() {
+                return &tier[index];
+            } else {
+                index -= tier.len();
+            }
         }
+        panic!("Index of strategy out of bound!");
     }
 
     pub fn is_verification(&self, index: usize) -> bool {
@@ -1,12 +1,17 @@
-use crate::errors::SpecificationNotFoundError;
+use crate::errors::SearchError;
 use crate::pack::{Rule, StrategyFactory, StrategyPack};
 use crate::specification::CombinatorialSpecification;
+use std::io;
 
 mod classdb;
 mod equiv_db;
 mod queue;
 pub mod ruledb;
 
+pub use classdb::{
+    ClassInfo, ClassStorage, DiskStorage, FactorySet, MemoryStorage, DEFAULT_CACHE_CAPACITY,
+};
+
 pub struct CombinatorialSpecificationSearcher<
     F: StrategyFactory,
     R: ruledb::RuleDB<F::StrategyType>,
@@ -23,61 +28,257 @@ where
     F: StrategyFactory,
     R: ruledb::RuleDB<F::StrategyType>,
 {
-    pub fn new(start_class: F::ClassType, pack: StrategyPack<F>, ruledb: R) -> Self {
-        let mut classdb = classdb::ClassDB::new();
-        let start_label = classdb.get_label_from_class_or_add(&start_class);
+    pub fn new(start_class: F::ClassType, pack: StrategyPack<F>, ruledb: R) -> Self
+    where
+        F::ClassType: 'static,
+    {
+        Self::with_class_storage(start_class, pack, ruledb, MemoryStorage::new())
+            .expect("The classes in memory can always be stored")
+    }
+
+    /// Create a searcher that keeps the classes it finds in the given storage,
+    /// for example a `DiskStorage` for searches whose classes do not fit in
+    /// memory.
+    pub fn with_class_storage(
+        start_class: F::ClassType,
+        pack: StrategyPack<F>,
+        ruledb: R,
+        storage: impl ClassStorage<F::ClassType> + 'static,
+    ) -> io::Result<Self> {
+        let mut classdb = classdb::ClassDB::with_storage(Box::new(storage));
+        let start_label = classdb.get_label_from_class_or_add(&start_class)?;
         let queue = queue::ClassQueue::new(pack, start_label);
-        Self {
+        let mut searcher = Self {
             start_label,
             queue,
             classdb,
             ruledb,
             last_wp_created_rule: None,
-        }
+        };
+        searcher.check_empty(start_label);
+        Ok(searcher)
     }
 
+    /// Return what the searcher knows about the class, if it was found.
+    pub fn get_class_info(&self, class: &F::ClassType) -> io::Result<Option<&ClassInfo>> {
+        let label = match self.classdb.get_label_from_class(class)? {
+            Some(label) => label,
+            None => return Ok(None),
+        };
+        Ok(self.classdb.get_info(label))
+    }
+
+    /// Expand classes until a specification of the start class is found. The
+    /// search stops on the first error of the class storage.
     pub fn auto_search(
         &mut self,
-    ) -> Result<CombinatorialSpecification<F::StrategyType>, SpecificationNotFoundError> {
+    ) -> Result<CombinatorialSpecification<F::StrategyType>, SearchError> {
         loop {
-            self.expand_once();
+            self.expand_once()?;
             match self
                 .ruledb
                 .get_specification(self.start_label, &self.classdb)
             {
-                Ok(spec) => {
-                    return Ok(spec);
-                }
-                Err(_) => {}
+                Err(SearchError::SpecificationNotFound(_)) => continue,
+                result => return result,
             }
         }
     }
 
-    fn expand_once(&mut self) {
+    fn expand_once(&mut self) -> io::Result<()> {
         let wp = self
             .queue
             .next(self.last_wp_created_rule)
             .expect("Queue is empty");
         let class = self
             .classdb
-            .get_class_from_label(wp.class_label)
+            .get_class_from_label(wp.class_label)?
             .expect("Class label not found");
         let rules = wp.factory.apply(&class);
+        self.classdb
+            .add_applied_factory(wp.class_label, wp.factory_index);
         self.last_wp_created_rule = Some(false);
         for rule in rules.into_iter() {
-            self.add_rule(rule);
+            self.add_rule(rule)?;
             self.last_wp_created_rule = Some(true);
         }
+        Ok(())
     }
 
-    fn add_rule(&mut self, rule: Rule<F::StrategyType>) {
-        let start = self.classdb.get_label_from_class_or_add(rule.get_parent());
-        let ends: Vec<_> = rule
+    fn add_rule(&mut self, rule: Rule<F::StrategyType>) -> io::Result<()> {
+        let start = self
+            .classdb
+            .get_label_from_class_or_add(rule.get_parent())?;
+        let ends = rule
             .get_children()
             .iter()
             .map(|c| self.classdb.get_label_from_class_or_add(c))
-            .collect();
-        ends.iter().map(|l| self.queue.add(*l)).last();
+            .collect::<io::Result<Vec<_>>>()?;
+        for &end in ends.iter() {
+            self.queue.add(end);
+            self.check_empty(end);
+        }
+        self.classdb.add_rule(start, ends.len());
         self.ruledb.add(start, ends, rule);
+        Ok(())
+    }
+
+    /// Only try the verification strategies on the class if it is known to be
+    /// empty.
+    fn check_empty(&mut self, label: usize) {
+        if self.classdb.get_info(label).unwrap().empty == Some(true) {
+            self.queue.set_empty(label);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::{AvoidingWithPrefix, WordStrategy};
+
+    #[test]
+    fn class_info_test() {
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let start_class =
+            AvoidingWithPrefix::new(String::new(), patterns.clone(), alphabet.clone());
+        let pack = StrategyPack {
+            initials: vec![WordStrategy::RemoveFrontOfPrefix],
+            inferrals: vec![],
+            expansions: vec![vec![WordStrategy::Expansion]],
+            verifications: vec![WordStrategy::Empty, WordStrategy::Atom],
+        };
+        let ruledb = ruledb::SimpleRuleDB::with_seed(0);
+        let mut searcher =
+            CombinatorialSpecificationSearcher::new(start_class.clone(), pack, ruledb);
+        searcher.auto_search().unwrap();
+        let info = searcher.get_class_info(&start_class).unwrap().unwrap();
+        assert_eq!(info.empty, Some(false));
+        assert!(!info.verified);
+        assert_eq!(info.num_rules, 1);
+        assert_eq!(
+            info.applied_factories.iter().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        let empty = AvoidingWithPrefix::new(String::from("aa"), patterns, alphabet);
+        let info = searcher.get_class_info(&empty).unwrap().unwrap();
+        assert_eq!(info.empty, Some(true));
+        assert!(info.verified);
+        assert!(info.applied_factories.iter().all(|i| i < 2));
+    }
+
+    /// Storage that fails once it holds a given number of classes.
+    struct FullStorage {
+        storage: MemoryStorage<AvoidingWithPrefix>,
+        capacity: usize,
+    }
+
+    impl ClassStorage<AvoidingWithPrefix> for FullStorage {
+        fn push(&mut self, class: &AvoidingWithPrefix) -> io::Result<()> {
+            if self.storage.len() == self.capacity {
+                return Err(io::Error::other("The storage is full"));
+            }
+            self.storage.push(class)
+        }
+
+        fn get(&self, label: usize) -> io::Result<Option<AvoidingWithPrefix>> {
+            self.storage.get(label)
+        }
+
+        fn find(&self, labels: &[usize], class: &AvoidingWithPrefix) -> io::Result<Option<usize>> {
+            self.storage.find(labels, class)
+        }
+
+        fn len(&self) -> usize {
+            self.storage.len()
+        }
+    }
+
+    #[test]
+    fn storage_error_test() {
+        let start_class = AvoidingWithPrefix::new(
+            String::new(),
+            vec![String::from("aba"), String::from("bb")],
+            vec!['a', 'b'],
+        );
+        let pack = || StrategyPack {
+            initials: vec![WordStrategy::RemoveFrontOfPrefix],
+            inferrals: vec![],
+            expansions: vec![vec![WordStrategy::Expansion]],
+            verifications: vec![WordStrategy::Empty, WordStrategy::Atom],
+        };
+        let storage = |capacity| FullStorage {
+            storage: MemoryStorage::new(),
+            capacity,
+        };
+        assert!(CombinatorialSpecificationSearcher::with_class_storage(
+            start_class.clone(),
+            pack(),
+            ruledb::SimpleRuleDB::with_seed(0),
+            storage(0),
+        )
+        .is_err());
+        let mut searcher = CombinatorialSpecificationSearcher::with_class_storage(
+            start_class,
+            pack(),
+            ruledb::SimpleRuleDB::with_seed(0),
+            storage(3),
+        )
+        .unwrap();
+        assert!(matches!(searcher.auto_search(), Err(SearchError::Io(_))));
+    }
+
+    /// Return the parent, the strategy and the children of each rule.
+    fn rules(
+        spec: CombinatorialSpecification<WordStrategy>,
+    ) -> Vec<(AvoidingWithPrefix, Vec<AvoidingWithPrefix>, String)> {
+        spec.rules
+            .into_iter()
+            .map(|rule| {
+                let parent = rule.get_parent().clone();
+                let children = rule.get_children().clone();
+                let strategy = serde_json::to_string(&rule.get_strategy()).unwrap();
+                (parent, children, strategy)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disk_storage_test() {
+        let path = std::env::temp_dir().join(format!(
+            "comb_spec_searcher_{}_searcher_disk_storage_test",
+            std::process::id()
+        ));
+        let start_class = AvoidingWithPrefix::new(
+            String::new(),
+            vec![String::from("aba"), String::from("bb")],
+            vec!['a', 'b'],
+        );
+        let pack = || StrategyPack {
+            initials: vec![WordStrategy::RemoveFrontOfPrefix],
+            inferrals: vec![],
+            expansions: vec![vec![WordStrategy::Expansion]],
+            verifications: vec![WordStrategy::Empty, WordStrategy::Atom],
+        };
+        let mut searcher = CombinatorialSpecificationSearcher::new(
+            start_class.clone(),
+            pack(),
+            ruledb::SimpleRuleDB::with_seed(0),
+        );
+        let expected = searcher.auto_search().unwrap();
+        let storage = DiskStorage::with_cache_capacity(&path, 2).unwrap();
+        let mut searcher = CombinatorialSpecificationSearcher::with_class_storage(
+            start_class.clone(),
+            pack(),
+            ruledb::SimpleRuleDB::with_seed(0),
+            storage,
+        )
+        .unwrap();
+        let spec = searcher.auto_search().unwrap();
+        assert_eq!(spec.root, expected.root);
+        assert_eq!(rules(spec), rules(expected));
+        assert!(searcher.get_class_info(&start_class).unwrap().is_some());
+        std::fs::remove_file(path).unwrap();
     }
 }
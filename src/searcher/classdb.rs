@@ -1,30 +1,225 @@
 use crate::combinatorial_class::CombinatorialClass;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::io;
+use std::time::{Duration, Instant};
 
+mod disk;
+mod memory;
+
+pub use disk::{DiskStorage, DEFAULT_CACHE_CAPACITY};
+pub use memory::MemoryStorage;
+
+/// What the searcher knows about a class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassInfo {
+    /// Whether the class is empty, or `None` if it is not known.
+    pub empty: Option<bool>,
+    /// Whether the class is the parent of a rule without children.
+    pub verified: bool,
+    /// The indices in the pack of the strategy factories applied to the class.
+    pub applied_factories: FactorySet,
+    /// The number of rules with the class as parent.
+    pub num_rules: usize,
+    /// The time since the creation of the database when the class was added.
+    pub discovered: Duration,
+}
+
+/// Set of indices of strategy factories in a pack.
+///
+/// The first 64 indices are kept inline, so a set only allocates for the packs
+/// with more factories.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FactorySet {
+    first: u64,
+    rest: Box<[u64]>,
+}
+
+impl FactorySet {
+    pub fn insert(&mut self, index: usize) {
+        if index < 64 {
+            self.first |= 1 << index;
+            return;
+        }
+        let word = index / 64 - 1;
+        if word >= self.rest.len() {
+            let mut rest = self.rest.to_vec();
+            rest.resize(word + 1, 0);
+            self.rest = rest.into_boxed_slice();
+        }
+        self.rest[word] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        let bits = match index / 64 {
+            0 => self.first,
+            word => self.rest.get(word - 1).cloned().unwrap_or(0),
+        };
+        bits >> (index % 64) & 1 == 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.words().map(|bits| bits.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the indices in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits >> bit & 1 == 1)
+                .map(move |bit| word * 64 + bit)
+        })
+    }
+
+    fn words(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::once(self.first).chain(self.rest.iter().cloned())
+    }
+}
+
+/// Labels of the classes by hash.
+///
+/// Almost every hash belongs to a single class, so the first label of each hash
+/// is kept inline and the labels of the later classes with the same hash in a
+/// side table.
+#[derive(Default)]
+struct LabelIndex {
+    first: HashMap<u64, usize>,
+    collisions: HashMap<u64, Vec<usize>>,
+}
+
+impl LabelIndex {
+    fn insert(&mut self, hash: u64, label: usize) {
+        match self.first.entry(hash) {
+            Entry::Vacant(entry) => {
+                entry.insert(label);
+            }
+            Entry::Occupied(_) => self.collisions.entry(hash).or_default().push(label),
+        }
+    }
+
+    /// Return the first label with the hash and the labels of the later classes
+    /// with the same hash.
+    fn get(&self, hash: u64) -> Option<(usize, &[usize])> {
+        let first = *self.first.get(&hash)?;
+        let collisions = self.collisions.get(&hash).map_or(&[][..], |c| c.as_slice());
+        Some((first, collisions))
+    }
+}
+
+/// Storage of the classes of a `ClassDB` by label, the label of a class being its
+/// index in the order the classes were stored.
+///
+/// The errors of a storage outside of memory are passed on to the searcher.
+pub trait ClassStorage<C: CombinatorialClass> {
+    /// Store the class with the next label.
+    fn push(&mut self, class: &C) -> io::Result<()>;
+
+    /// Return the class with the given label.
+    fn get(&self, label: usize) -> io::Result<Option<C>>;
+
+    /// Return the first of the given labels whose class is the given class.
+    fn find(&self, labels: &[usize], class: &C) -> io::Result<Option<usize>>;
+
+    /// Return the number of classes stored.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Database of the classes found by the searcher. The label of a class is its
+/// index in the order the classes were added.
+///
+/// The classes are kept by a `ClassStorage`, in memory by default, while what is
+/// known about them is always kept in memory.
 pub struct ClassDB<C: CombinatorialClass> {
-    data: Vec<C>,
+    storage: Box<dyn ClassStorage<C>>,
+    infos: Vec<ClassInfo>,
+    created: Instant,
+    /// The labels of the classes by hash of the class, so that the classes are
+    /// only stored once.
+    index: LabelIndex,
+    hasher: RandomState,
 }
 
-impl<C: CombinatorialClass> ClassDB<C> {
+impl<C: CombinatorialClass + 'static> ClassDB<C> {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self::with_storage(Box::new(MemoryStorage::new()))
     }
+}
 
-    pub fn get_label_from_class_or_add(&mut self, class: &C) -> usize {
-        match self.get_label_from_class(class) {
-            Some(index) => index,
-            None => {
-                self.data.push(class.clone());
-                self.data.len() - 1
-            }
+impl<C: CombinatorialClass> ClassDB<C> {
+    pub fn with_storage(storage: Box<dyn ClassStorage<C>>) -> Self {
+        Self {
+            storage,
+            infos: Vec::new(),
+            created: Instant::now(),
+            index: LabelIndex::default(),
+            hasher: RandomState::new(),
         }
     }
 
-    pub fn get_label_from_class(&self, class: &C) -> Option<usize> {
-        self.data.iter().position(|x| x == class)
+    pub fn get_label_from_class_or_add(&mut self, class: &C) -> io::Result<usize> {
+        let hash = self.hasher.hash_one(class);
+        if let Some(label) = self.find_label(hash, class)? {
+            return Ok(label);
+        }
+        let label = self.storage.len();
+        self.storage.push(class)?;
+        self.infos.push(ClassInfo {
+            empty: class.is_empty(),
+            verified: false,
+            applied_factories: FactorySet::default(),
+            num_rules: 0,
+            discovered: self.created.elapsed(),
+        });
+        self.index.insert(hash, label);
+        Ok(label)
     }
 
-    pub fn get_class_from_label(&self, label: usize) -> Option<&C> {
-        self.data.get(label)
+    pub fn get_label_from_class(&self, class: &C) -> io::Result<Option<usize>> {
+        self.find_label(self.hasher.hash_one(class), class)
+    }
+
+    pub fn get_class_from_label(&self, label: usize) -> io::Result<Option<C>> {
+        self.storage.get(label)
+    }
+
+    pub fn get_info(&self, label: usize) -> Option<&ClassInfo> {
+        self.infos.get(label)
+    }
+
+    /// Record that the strategy factory with the given index was applied to the
+    /// class.
+    pub fn add_applied_factory(&mut self, label: usize, factory_index: usize) {
+        self.infos[label].applied_factories.insert(factory_index);
+    }
+
+    /// Record a rule with the class as parent.
+    pub fn add_rule(&mut self, label: usize, num_children: usize) {
+        let info = &mut self.infos[label];
+        info.num_rules += 1;
+        if num_children == 0 {
+            info.verified = true;
+        }
+    }
+
+    /// Find the label of the class with the given hash.
+    fn find_label(&self, hash: u64, class: &C) -> io::Result<Option<usize>> {
+        let (first, collisions) = match self.index.get(hash) {
+            Some(labels) => labels,
+            None => return Ok(None),
+        };
+        match self.storage.find(&[first], class)? {
+            Some(label) => Ok(Some(label)),
+            None => self.storage.find(collisions, class),
+        }
     }
 }
 
@@ -41,14 +236,121 @@ mod tests {
         let w2 = AvoidingWithPrefix::new(String::from("a"), patterns.clone(), alphabet.clone());
         let w3 = AvoidingWithPrefix::new(String::from("b"), patterns.clone(), alphabet.clone());
         let mut classdb = ClassDB::new();
-        assert_eq!(classdb.get_label_from_class(&w1), None);
-        assert_eq!(classdb.get_label_from_class_or_add(&w1), 0);
-        assert_eq!(classdb.get_label_from_class(&w1), Some(0));
-        assert_eq!(classdb.get_label_from_class_or_add(&w2), 1);
-        assert_eq!(classdb.get_label_from_class(&w1), Some(0));
-        assert_eq!(classdb.get_label_from_class(&w2), Some(1));
-        assert_eq!(classdb.get_label_from_class_or_add(&w3), 2);
-        assert_eq!(classdb.get_label_from_class(&w1), Some(0));
-        assert_eq!(classdb.get_label_from_class(&w2), Some(1));
+        assert_eq!(classdb.get_label_from_class(&w1).unwrap(), None);
+        assert_eq!(classdb.get_label_from_class_or_add(&w1).unwrap(), 0);
+        assert_eq!(classdb.get_label_from_class(&w1).unwrap(), Some(0));
+        assert_eq!(classdb.get_label_from_class_or_add(&w2).unwrap(), 1);
+        assert_eq!(classdb.get_label_from_class(&w1).unwrap(), Some(0));
+        assert_eq!(classdb.get_label_from_class(&w2).unwrap(), Some(1));
+        assert_eq!(classdb.get_label_from_class_or_add(&w3).unwrap(), 2);
+        assert_eq!(classdb.get_label_from_class(&w1).unwrap(), Some(0));
+        assert_eq!(classdb.get_label_from_class(&w2).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn class_info_test() {
+        let alphabet = vec!['a', 'b'];
+        let patterns = vec![String::from("aa")];
+        let w1 = AvoidingWithPrefix::new(String::from("b"), patterns.clone(), alphabet.clone());
+        let w2 = AvoidingWithPrefix::new(String::from("baa"), patterns, alphabet);
+        let mut classdb = ClassDB::new();
+        let l1 = classdb.get_label_from_class_or_add(&w1).unwrap();
+        let l2 = classdb.get_label_from_class_or_add(&w2).unwrap();
+        classdb.add_applied_factory(l1, 3);
+        classdb.add_rule(l1, 2);
+        classdb.add_rule(l2, 0);
+        let info = classdb.get_info(l1).unwrap();
+        assert_eq!(info.empty, Some(false));
+        assert!(!info.verified);
+        assert_eq!(info.applied_factories.iter().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(info.num_rules, 1);
+        let info = classdb.get_info(l2).unwrap();
+        assert_eq!(info.empty, Some(true));
+        assert!(info.verified);
+        assert!(info.discovered >= classdb.get_info(l1).unwrap().discovered);
+        assert_eq!(classdb.get_info(2), None);
+    }
+
+    #[test]
+    fn factory_set_test() {
+        let mut factories = FactorySet::default();
+        assert!(factories.is_empty());
+        for index in [130, 3, 64, 0, 3] {
+            factories.insert(index);
+        }
+        assert_eq!(factories.len(), 4);
+        assert_eq!(factories.iter().collect::<Vec<_>>(), vec![0, 3, 64, 130]);
+        assert!(factories.contains(64));
+        assert!(!factories.contains(63));
+        assert!(!factories.contains(1000));
+    }
+
+    #[test]
+    fn label_index_collision_test() {
+        let mut index = LabelIndex::default();
+        assert_eq!(index.get(7), None);
+        index.insert(7, 0);
+        index.insert(8, 1);
+        assert_eq!(index.get(7), Some((0, &[][..])));
+        index.insert(7, 2);
+        index.insert(7, 3);
+        assert_eq!(index.get(7), Some((0, &[2, 3][..])));
+        assert_eq!(index.get(8), Some((1, &[][..])));
+    }
+
+    /// A class without compact encoding is stored as it is.
+    #[test]
+    fn uncompressed_class_test() {
+        let alphabet = vec!['a', 'b'];
+        let patterns = vec![String::from("aaa")];
+        let w1 = AvoidingWithPrefix::new(String::from("ab"), patterns.clone(), alphabet.clone());
+        let w2 = AvoidingWithPrefix::new(String::from("ac"), patterns, alphabet);
+        let mut classdb = ClassDB::new();
+        assert_eq!(classdb.get_label_from_class_or_add(&w1).unwrap(), 0);
+        assert_eq!(classdb.get_label_from_class_or_add(&w2).unwrap(), 1);
+        assert_eq!(classdb.get_label_from_class_or_add(&w2).unwrap(), 1);
+        assert_eq!(classdb.get_class_from_label(0).unwrap(), Some(w1));
+        assert_eq!(classdb.get_class_from_label(1).unwrap(), Some(w2));
+        assert_eq!(classdb.get_class_from_label(2).unwrap(), None);
+    }
+
+    #[test]
+    fn many_classes_test() {
+        let patterns = vec![String::from("aaaaaaaaaaaaaaaaaaaa")];
+        let class = |i: usize| {
+            AvoidingWithPrefix::new(format!("{:b}", i), patterns.clone(), vec!['0', '1'])
+        };
+        let mut classdb = ClassDB::new();
+        for i in 0..100_000 {
+            assert_eq!(classdb.get_label_from_class_or_add(&class(i)).unwrap(), i);
+        }
+        for i in (0..100_000).step_by(997) {
+            assert_eq!(classdb.get_label_from_class_or_add(&class(i)).unwrap(), i);
+            assert_eq!(classdb.get_class_from_label(i).unwrap(), Some(class(i)));
+        }
+        assert_eq!(classdb.get_label_from_class(&class(100_000)).unwrap(), None);
+    }
+
+    #[test]
+    fn disk_storage_test() {
+        let path = std::env::temp_dir().join(format!(
+            "comb_spec_searcher_{}_classdb_disk_storage_test",
+            std::process::id()
+        ));
+        let patterns = vec![String::from("aaaaaaaaaaaaaaaaaaaa")];
+        let class = |i: usize| {
+            AvoidingWithPrefix::new(format!("{:b}", i), patterns.clone(), vec!['0', '1'])
+        };
+        let storage = DiskStorage::with_cache_capacity(&path, 100).unwrap();
+        let mut classdb = ClassDB::with_storage(Box::new(storage));
+        for i in 0..10_000 {
+            assert_eq!(classdb.get_label_from_class_or_add(&class(i)).unwrap(), i);
+        }
+        for i in (0..10_000).step_by(97) {
+            assert_eq!(classdb.get_label_from_class_or_add(&class(i)).unwrap(), i);
+            assert_eq!(classdb.get_class_from_label(i).unwrap(), Some(class(i)));
+        }
+        assert_eq!(classdb.get_label_from_class(&class(10_000)).unwrap(), None);
+        std::fs::remove_file(path).unwrap();
     }
 }
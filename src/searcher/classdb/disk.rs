@@ -0,0 +1,303 @@
+use super::ClassStorage;
+use crate::combinatorial_class::{ClassCodec, CombinatorialClass};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The number of decoded classes kept in memory by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// The number of encoded bytes kept in memory before they are written to the file.
+const WRITE_BUFFER_SIZE: usize = 1 << 16;
+
+/// Where a class is stored.
+enum Location {
+    /// The index of the class in the classes kept in memory.
+    Class(usize),
+    /// The position of the compact encoding of the class in the file.
+    File { offset: u64, len: usize },
+}
+
+/// Storage of the classes in an append-only file, for searches whose classes do
+/// not fit in memory. Only the positions of the classes in the file and a bounded
+/// cache of the most recently used classes are kept in memory.
+///
+/// The encodings are appended to a buffer that is written to the file once it is
+/// full, and the classes still in the buffer are read from it.
+///
+/// Classes without a compact encoding cannot be written to the file, so they are
+/// kept in memory.
+pub struct DiskStorage<C> {
+    codec: Option<Box<dyn ClassCodec<C>>>,
+    file: File,
+    /// The number of bytes written to the file.
+    written: u64,
+    /// The encodings that follow the bytes written to the file.
+    buffer: Vec<u8>,
+    classes: Vec<C>,
+    locations: Vec<Location>,
+    cache: RefCell<LruCache<C>>,
+}
+
+impl<C: CombinatorialClass> DiskStorage<C> {
+    /// Store the classes in a new file at the given path, replacing any existing
+    /// file.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::with_cache_capacity(path, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Store the classes in a new file at the given path, keeping at most
+    /// `capacity` decoded classes in memory.
+    pub fn with_cache_capacity<P: AsRef<Path>>(path: P, capacity: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            codec: C::codec(),
+            file,
+            written: 0,
+            buffer: Vec::new(),
+            classes: Vec::new(),
+            locations: Vec::new(),
+            cache: RefCell::new(LruCache::new(capacity)),
+        })
+    }
+
+    /// Write the buffer at the end of the file.
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.written))?;
+        self.file.write_all(&self.buffer)?;
+        self.written += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        if offset >= self.written {
+            let start = (offset - self.written) as usize;
+            return Ok(self.buffer[start..start + len].to_vec());
+        }
+        let mut bytes = vec![0; len];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl<C: CombinatorialClass> ClassStorage<C> for DiskStorage<C> {
+    fn push(&mut self, class: &C) -> io::Result<()> {
+        let encoding = self.codec.as_mut().and_then(|codec| {
+            codec.intern(class);
+            codec.encode(class)
+        });
+        let location = match encoding {
+            Some(encoding) => {
+                if self.buffer.len() + encoding.len() > WRITE_BUFFER_SIZE {
+                    self.flush()?;
+                }
+                let offset = self.written + self.buffer.len() as u64;
+                self.buffer.extend(&encoding);
+                // A new class is usually expanded soon after.
+                self.cache
+                    .get_mut()
+                    .insert(self.locations.len(), class.clone());
+                Location::File {
+                    offset,
+                    len: encoding.len(),
+                }
+            }
+            None => {
+                self.classes.push(class.clone());
+                Location::Class(self.classes.len() - 1)
+            }
+        };
+        self.locations.push(location);
+        Ok(())
+    }
+
+    fn get(&self, label: usize) -> io::Result<Option<C>> {
+        let (offset, len) = match self.locations.get(label) {
+            None => return Ok(None),
+            Some(Location::Class(idx)) => return Ok(Some(self.classes[*idx].clone())),
+            Some(Location::File { offset, len }) => (*offset, *len),
+        };
+        let mut cache = self.cache.borrow_mut();
+        if let Some(class) = cache.get(label) {
+            return Ok(Some(class.clone()));
+        }
+        let codec = self.codec.as_ref().unwrap();
+        let class = codec.decode(&self.read(offset, len)?);
+        cache.insert(label, class.clone());
+        Ok(Some(class))
+    }
+
+    fn find(&self, labels: &[usize], class: &C) -> io::Result<Option<usize>> {
+        let encoding = self.codec.as_ref().and_then(|codec| codec.encode(class));
+        for &label in labels {
+            let is_class = match (&self.locations[label], &encoding) {
+                (Location::Class(idx), None) => self.classes[*idx] == *class,
+                (Location::File { offset, len }, Some(encoding)) => {
+                    if *len != encoding.len() {
+                        continue;
+                    }
+                    let cached = self.cache.borrow_mut().get(label).map(|c| c == class);
+                    match cached {
+                        Some(is_class) => is_class,
+                        None => self.read(*offset, *len)? == *encoding,
+                    }
+                }
+                _ => false,
+            };
+            if is_class {
+                return Ok(Some(label));
+            }
+        }
+        Ok(None)
+    }
+
+    fn len(&self) -> usize {
+        self.locations.len()
+    }
+}
+
+/// Cache of the most recently used classes by label.
+struct LruCache<C> {
+    capacity: usize,
+    /// The number of uses so far, used to order the uses.
+    time: u64,
+    entries: HashMap<usize, (C, u64)>,
+    /// The labels of the cached classes by time of last use.
+    by_last_use: BTreeMap<u64, usize>,
+}
+
+impl<C> LruCache<C> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            time: 0,
+            entries: HashMap::new(),
+            by_last_use: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, label: usize) -> Option<&C> {
+        let (class, last_use) = self.entries.get_mut(&label)?;
+        self.by_last_use.remove(last_use);
+        *last_use = self.time;
+        self.by_last_use.insert(self.time, label);
+        self.time += 1;
+        Some(class)
+    }
+
+    /// Add the class to the cache, removing the least recently used class if the
+    /// cache is full.
+    fn insert(&mut self, label: usize, class: C) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, last_use)) = self.entries.insert(label, (class, self.time)) {
+            self.by_last_use.remove(&last_use);
+        }
+        self.by_last_use.insert(self.time, label);
+        self.time += 1;
+        if self.entries.len() > self.capacity {
+            let (_, oldest) = self.by_last_use.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::word::AvoidingWithPrefix;
+    use std::path::PathBuf;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "comb_spec_searcher_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn lru_cache_test() {
+        let mut cache = LruCache::new(2);
+        cache.insert(0, 'a');
+        cache.insert(1, 'b');
+        assert_eq!(cache.get(0), Some(&'a'));
+        cache.insert(2, 'c');
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(0), Some(&'a'));
+        assert_eq!(cache.get(2), Some(&'c'));
+        cache.insert(3, 'd');
+        assert_eq!(cache.get(0), None);
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn disk_storage_test() {
+        let path = path("disk_storage_test");
+        let patterns = vec![String::from("aaa")];
+        let class = |prefix: &str| {
+            AvoidingWithPrefix::new(prefix.to_string(), patterns.clone(), vec!['a', 'b'])
+        };
+        let uncompressed =
+            AvoidingWithPrefix::new(String::from("c"), patterns.clone(), vec!['a', 'b']);
+        let mut storage = DiskStorage::with_cache_capacity(&path, 1).unwrap();
+        let prefixes = ["", "a", "ab", "bab", "aabba"];
+        for prefix in prefixes {
+            storage.push(&class(prefix)).unwrap();
+        }
+        storage.push(&uncompressed).unwrap();
+        assert_eq!(storage.len(), 6);
+        assert_eq!(storage.classes, vec![uncompressed.clone()]);
+        let all_labels: Vec<_> = (0..6).collect();
+        let check = |storage: &DiskStorage<_>| {
+            for (label, prefix) in prefixes.iter().enumerate() {
+                assert_eq!(storage.get(label).unwrap(), Some(class(prefix)));
+                let found = storage.find(&all_labels, &class(prefix)).unwrap();
+                assert_eq!(found, Some(label));
+                let others = [(label + 1) % 5, 5];
+                assert_eq!(storage.find(&others, &class(prefix)).unwrap(), None);
+            }
+            assert_eq!(storage.get(5).unwrap(), Some(uncompressed.clone()));
+            assert_eq!(storage.find(&all_labels, &uncompressed).unwrap(), Some(5));
+            assert_eq!(storage.get(6).unwrap(), None);
+        };
+        // The classes are first read from the buffer, then from the file.
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        check(&storage);
+        let len = storage.buffer.len() as u64;
+        storage.flush().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        check(&storage);
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn write_buffer_test() {
+        let path = path("write_buffer_test");
+        let patterns = vec![String::from("aaaaaaaaaaaaaaaaaaaa")];
+        let class = |i: usize| {
+            AvoidingWithPrefix::new(format!("{:b}", i), patterns.clone(), vec!['0', '1'])
+        };
+        let mut storage = DiskStorage::with_cache_capacity(&path, 10).unwrap();
+        for i in 0..30_000 {
+            storage.push(&class(i)).unwrap();
+        }
+        assert!(storage.written > 0);
+        assert!(storage.buffer.len() <= WRITE_BUFFER_SIZE);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), storage.written);
+        for i in (0..30_000).step_by(101) {
+            assert_eq!(storage.get(i).unwrap(), Some(class(i)));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use super::ClassStorage;
+use crate::combinatorial_class::{ClassCodec, CombinatorialClass};
+use std::io;
+
+/// Where a class is stored in memory.
+enum Location {
+    /// The index of the class in the classes stored as they are.
+    Class(usize),
+    /// The range of the compact encoding of the class in the encodings.
+    Bytes { start: usize, end: usize },
+}
+
+/// Storage of the classes in memory.
+///
+/// Classes with a compact encoding are stored encoded, one after the other, and
+/// decoded when they are retrieved.
+pub struct MemoryStorage<C> {
+    codec: Option<Box<dyn ClassCodec<C>>>,
+    classes: Vec<C>,
+    bytes: Vec<u8>,
+    locations: Vec<Location>,
+}
+
+impl<C: CombinatorialClass> MemoryStorage<C> {
+    pub fn new() -> Self {
+        Self {
+            codec: C::codec(),
+            classes: Vec::new(),
+            bytes: Vec::new(),
+            locations: Vec::new(),
+        }
+    }
+}
+
+impl<C: CombinatorialClass> Default for MemoryStorage<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: CombinatorialClass> ClassStorage<C> for MemoryStorage<C> {
+    fn push(&mut self, class: &C) -> io::Result<()> {
+        let encoding = self.codec.as_mut().and_then(|codec| {
+            codec.intern(class);
+            codec.encode(class)
+        });
+        let location = match encoding {
+            Some(encoding) => {
+                let start = self.bytes.len();
+                self.bytes.extend(encoding);
+                Location::Bytes {
+                    start,
+                    end: self.bytes.len(),
+                }
+            }
+            None => {
+                self.classes.push(class.clone());
+                Location::Class(self.classes.len() - 1)
+            }
+        };
+        self.locations.push(location);
+        Ok(())
+    }
+
+    fn get(&self, label: usize) -> io::Result<Option<C>> {
+        let class = self.locations.get(label).map(|location| match location {
+            Location::Class(idx) => self.classes[*idx].clone(),
+            Location::Bytes { start, end } => {
+                let codec = self.codec.as_ref().unwrap();
+                codec.decode(&self.bytes[*start..*end])
+            }
+        });
+        Ok(class)
+    }
+
+    fn find(&self, labels: &[usize], class: &C) -> io::Result<Option<usize>> {
+        let encoding = self.codec.as_ref().and_then(|codec| codec.encode(class));
+        let label = labels
+            .iter()
+            .find(|&&label| match (&self.locations[label], &encoding) {
+                (Location::Class(idx), None) => self.classes[*idx] == *class,
+                (Location::Bytes { start, end }, Some(encoding)) => {
+                    self.bytes[*start..*end] == *encoding
+                }
+                _ => false,
+            })
+            .cloned();
+        Ok(label)
+    }
+
+    fn len(&self) -> usize {
+        self.locations.len()
+    }
+}
@@ -1,4 +1,5 @@
 use super::ruledb::RuleLabel;
+use crate::errors::PathNotFoundError;
 use pathfinding::prelude::bfs;
 use std::cmp;
 
@@ -65,14 +66,16 @@ impl UnionFind {
 #[derive(Debug)]
 pub struct EquivDB {
     union_find: UnionFind,
-    edges: Vec<(usize, usize)>,
+    /// Adjacency lists of the equivalences, grouped by the root of the equivalence
+    /// class.
+    components: HashMap<usize, HashMap<usize, Vec<usize>>>,
 }
 
 impl EquivDB {
     pub fn new() -> Self {
         Self {
             union_find: UnionFind::new(),
-            edges: Vec::new(),
+            components: HashMap::new(),
         }
     }
 
@@ -81,8 +84,21 @@ impl EquivDB {
     }
 
     pub fn union(&mut self, label1: usize, label2: usize) {
-        self.edges.push((label1, label2));
-        self.union_find.union(label1, label2)
+        let (root1, root2) = (self.find(label1), self.find(label2));
+        self.union_find.union(label1, label2);
+        let root = self.find(label1);
+        let mut component = self.components.remove(&root).unwrap_or_default();
+        if root1 != root2 {
+            // The root of the heaviest class is kept, so the lightest is merged in.
+            let other_root = if root == root1 { root2 } else { root1 };
+            let other_component = self.components.remove(&other_root).unwrap_or_default();
+            for (label, neighbours) in other_component {
+                component.entry(label).or_default().extend(neighbours);
+            }
+        }
+        component.entry(label1).or_default().push(label2);
+        component.entry(label2).or_default().push(label1);
+        self.components.insert(root, component);
     }
 
     /// Convert the given rule to it's equivalence label version
@@ -92,25 +108,21 @@ impl EquivDB {
         RuleLabel::new(eqv_parent, eqv_children)
     }
 
-    /// Find a sequence equivalence path between to class
-    pub fn find_path(&mut self, start: usize, end: usize) -> Vec<usize> {
-        let connected_component: Vec<_> = self
-            .edges
-            .iter()
-            .filter(|(v1, _)| self.union_find.are_equivalent(*v1, start))
-            .collect();
+    /// Find a shortest sequence of classes from start to end where two consecutive
+    /// classes are related by an equivalence.
+    pub fn find_path(&mut self, start: usize, end: usize) -> Result<Vec<usize>, PathNotFoundError> {
+        if !self.union_find.are_equivalent(start, end) {
+            return Err(PathNotFoundError {});
+        }
+        let root = self.find(start);
+        let no_equivalence = HashMap::new();
+        let component = self.components.get(&root).unwrap_or(&no_equivalence);
         bfs(
             &start,
-            |v| {
-                connected_component
-                    .iter()
-                    .filter(|(v1, v2)| v1 == v || v2 == v)
-                    .map(|(v1, v2)| if v1 == v { *v2 } else { *v1 })
-                    .collect::<Vec<_>>()
-            },
+            |v| component.get(v).into_iter().flatten().cloned(),
             |v| *v == end,
         )
-        .unwrap()
+        .ok_or(PathNotFoundError {})
     }
 }
 
@@ -139,4 +151,28 @@ mod tests {
         assert_eq!(db.find(2), db.find(3));
         assert_eq!(db.find(3), db.find(4));
     }
+
+    #[test]
+    fn find_path_test() {
+        let mut db = EquivDB::new();
+        db.union(1, 2);
+        db.union(3, 4);
+        db.union(2, 3);
+        db.union(5, 6);
+        db.union(4, 7);
+        assert_eq!(db.find_path(1, 1).unwrap(), vec![1]);
+        assert_eq!(db.find_path(2, 4).unwrap(), vec![2, 3, 4]);
+        assert_eq!(db.find_path(7, 1).unwrap(), vec![7, 4, 3, 2, 1]);
+        assert_eq!(db.find_path(6, 5).unwrap(), vec![6, 5]);
+        assert_eq!(db.find_path(8, 8).unwrap(), vec![8]);
+    }
+
+    #[test]
+    fn find_path_not_equivalent_test() {
+        let mut db = EquivDB::new();
+        db.union(1, 2);
+        db.union(3, 4);
+        assert!(db.find_path(1, 3).is_err());
+        assert!(db.find_path(1, 5).is_err());
+    }
 }
@@ -5,6 +5,7 @@ use std::collections::{HashSet, VecDeque};
 pub struct WorkPacket<'a, F: StrategyFactory> {
     pub class_label: usize,
     pub factory: &'a F,
+    pub factory_index: usize,
 }
 
 #[derive(Debug, PartialEq)]
@@ -21,6 +22,7 @@ impl WorkPacketInternal {
         WorkPacket {
             class_label: self.class_label,
             factory: pack.get_strategy_factory(self.factory_index),
+            factory_index: self.factory_index,
         }
     }
 }
@@ -30,22 +32,26 @@ pub struct ClassQueue<F: StrategyFactory> {
     verification_queue: VecDeque<WorkPacketInternal>,
     inferral_queue: VecDeque<WorkPacketInternal>,
     initial_queue: VecDeque<WorkPacketInternal>,
-    expansion_queue: VecDeque<WorkPacketInternal>,
+    /// One queue per tier of expansions.
+    expansion_queues: Vec<VecDeque<WorkPacketInternal>>,
     ignore: HashSet<usize>, // Classes that should not be yielded anymore
     added: HashSet<usize>,  // Classes already added to the queue
+    empty: HashSet<usize>,  // Classes that are only yielded with verifications
     last_wp: Option<WorkPacketInternal>,
 }
 
 impl<F: StrategyFactory> ClassQueue<F> {
     pub fn new(pack: StrategyPack<F>, start_label: usize) -> Self {
+        let expansion_queues = pack.expansions.iter().map(|_| VecDeque::new()).collect();
         let mut queue = Self {
             pack,
             verification_queue: VecDeque::new(),
             inferral_queue: VecDeque::new(),
             initial_queue: VecDeque::new(),
-            expansion_queue: VecDeque::new(),
+            expansion_queues,
             ignore: HashSet::new(),
             added: HashSet::new(),
+            empty: HashSet::new(),
             last_wp: None,
         };
         queue.add(start_label);
@@ -57,7 +63,7 @@ impl<F: StrategyFactory> ClassQueue<F> {
             return;
         }
         let mut factory_index = 0;
-        while let Some(_) = self.pack.verifications.get(factory_index) {
+        while self.pack.verifications.get(factory_index).is_some() {
             self.verification_queue.push_back(WorkPacketInternal {
                 class_label,
                 factory_index,
@@ -65,7 +71,7 @@ impl<F: StrategyFactory> ClassQueue<F> {
             factory_index += 1;
         }
         let mut cum = factory_index;
-        while let Some(_) = self.pack.inferrals.get(factory_index - cum) {
+        while self.pack.inferrals.get(factory_index - cum).is_some() {
             self.inferral_queue.push_back(WorkPacketInternal {
                 class_label,
                 factory_index,
@@ -73,20 +79,26 @@ impl<F: StrategyFactory> ClassQueue<F> {
             factory_index += 1;
         }
         cum = factory_index;
-        while let Some(_) = self.pack.initials.get(factory_index - cum) {
+        while self.pack.initials.get(factory_index - cum).is_some() {
             self.initial_queue.push_back(WorkPacketInternal {
                 class_label,
                 factory_index,
             });
             factory_index += 1;
         }
-        cum = factory_index;
-        while let Some(_) = self.pack.expansions.get(factory_index - cum) {
-            self.expansion_queue.push_back(WorkPacketInternal {
-                class_label,
-                factory_index,
-            });
-            factory_index += 1;
+        for (tier, queue) in self
+            .pack
+            .expansions
+            .iter()
+            .zip(self.expansion_queues.iter_mut())
+        {
+            for _ in tier.iter() {
+                queue.push_back(WorkPacketInternal {
+                    class_label,
+                    factory_index,
+                });
+                factory_index += 1;
+            }
         }
     }
 
@@ -94,11 +106,19 @@ impl<F: StrategyFactory> ClassQueue<F> {
         self.ignore.insert(label);
     }
 
-    pub fn next(&mut self, last_wp_created_rule: Option<bool>) -> Option<WorkPacket<F>> {
+    /// Only yield the class with verification strategies from now on, since it is
+    /// known to be empty.
+    pub fn set_empty(&mut self, label: usize) {
+        self.empty.insert(label);
+    }
+
+    pub fn next(&mut self, last_wp_created_rule: Option<bool>) -> Option<WorkPacket<'_, F>> {
         self.decide_if_ignore(last_wp_created_rule);
         loop {
             let next = self.next_no_ignore()?;
-            if !self.ignore.contains(&next.class_label) {
+            let skip_empty = self.empty.contains(&next.class_label)
+                && !self.pack.is_verification(next.factory_index);
+            if !self.ignore.contains(&next.class_label) && !skip_empty {
                 let external_wp = next.make_external(&self.pack);
                 self.last_wp = Some(next);
                 return Some(external_wp);
@@ -115,7 +135,9 @@ impl<F: StrategyFactory> ClassQueue<F> {
         } else if let Some(wp) = self.initial_queue.pop_front() {
             return Some(wp);
         }
-        self.expansion_queue.pop_front()
+        self.expansion_queues
+            .iter_mut()
+            .find_map(|queue| queue.pop_front())
     }
 
     /// Decide whether the class from the last work packet should now be ignore based on whether the
@@ -143,7 +165,7 @@ mod tests {
     use crate::combinatorial_class::CombinatorialClass;
     use crate::pack::{Rule, Strategy, StrategyPack};
 
-    #[derive(Debug, PartialEq, Clone)]
+    #[derive(Debug, PartialEq, Eq, Hash, Clone)]
     struct MockClass {}
 
     impl CombinatorialClass for MockClass {}
@@ -163,20 +185,28 @@ mod tests {
     impl Strategy for MockStrategy {
         type ClassType = MockClass;
 
-        fn decompose(&self, comb_class: &MockClass) -> Vec<MockClass> {
+        fn decompose(&self, _comb_class: &MockClass) -> Vec<MockClass> {
             unimplemented!();
         }
 
         fn is_equivalence(&self) -> bool {
             unimplemented!();
         }
+
+        fn shifts(&self, _comb_class: &MockClass) -> Vec<i32> {
+            unimplemented!();
+        }
+
+        fn is_reversible(&self) -> bool {
+            unimplemented!();
+        }
     }
 
     impl StrategyFactory for MockStrategy {
         type ClassType = MockClass;
         type StrategyType = MockStrategy;
 
-        fn apply(&self, class: &MockClass) -> Vec<Rule<MockStrategy>> {
+        fn apply(&self, _class: &MockClass) -> Vec<Rule<MockStrategy>> {
             unimplemented!();
         }
     }
@@ -185,7 +215,7 @@ mod tests {
         StrategyPack {
             initials: vec![MockStrategy::Initial1, MockStrategy::Initial2],
             inferrals: vec![MockStrategy::Inferral1, MockStrategy::Inferral2],
-            expansions: vec![MockStrategy::Expansion1, MockStrategy::Expansion2],
+            expansions: vec![vec![MockStrategy::Expansion1, MockStrategy::Expansion2]],
             verifications: vec![MockStrategy::Verification1, MockStrategy::Verification2],
         }
     }
@@ -224,66 +254,82 @@ mod tests {
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Verification1,
+                factory_index: 0,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Verification2,
+                factory_index: 1,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Verification1,
+                factory_index: 0,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Verification2,
+                factory_index: 1,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Inferral1,
+                factory_index: 2,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Inferral2,
+                factory_index: 3,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Inferral1,
+                factory_index: 2,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Inferral2,
+                factory_index: 3,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Initial1,
+                factory_index: 4,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Initial2,
+                factory_index: 5,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Initial1,
+                factory_index: 4,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Initial2,
+                factory_index: 5,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Expansion1,
+                factory_index: 6,
             },
             WorkPacket {
                 class_label: 0,
                 factory: &MockStrategy::Expansion2,
+                factory_index: 7,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Expansion1,
+                factory_index: 6,
             },
             WorkPacket {
                 class_label: 1,
                 factory: &MockStrategy::Expansion2,
+                factory_index: 7,
             },
         ]
         .into_iter();
@@ -306,21 +352,24 @@ mod tests {
             queue.next(Some(false)),
             Some(WorkPacket {
                 class_label: 3,
-                factory: &MockStrategy::Verification1
+                factory: &MockStrategy::Verification1,
+                factory_index: 0
             })
         );
         assert_eq!(
             queue.next(Some(false)),
             Some(WorkPacket {
                 class_label: 3,
-                factory: &MockStrategy::Verification2
+                factory: &MockStrategy::Verification2,
+                factory_index: 1
             })
         );
         assert_eq!(
             queue.next(Some(false)),
             Some(WorkPacket {
                 class_label: 0,
-                factory: &MockStrategy::Inferral2
+                factory: &MockStrategy::Inferral2,
+                factory_index: 3
             })
         );
     }
@@ -355,6 +404,19 @@ mod tests {
         assert_eq!(queue.next(Some(true)), None);
     }
 
+    #[test]
+    fn only_verifications_for_empty_class() {
+        let mut queue = ClassQueue::new(pack(), 0);
+        queue.set_empty(0);
+        queue.add(1);
+        queue.set_empty(1);
+        let mut wps = vec![queue.next(None).unwrap().factory_index];
+        while let Some(wp) = queue.next(Some(false)) {
+            wps.push(wp.factory_index);
+        }
+        assert_eq!(wps, vec![0, 1, 0, 1]);
+    }
+
     #[test]
     fn keep_yielding_after_initial_and_expansion() {
         let mut queue = ClassQueue::new(pack(), 0);
@@ -374,4 +436,43 @@ mod tests {
             .expect("Should yield after successful initial"); //exp2
         assert_eq!(queue.next(Some(true)), None)
     }
+
+    #[test]
+    fn expansion_tiers_test() {
+        let mut pack = pack();
+        pack.expansions = vec![
+            vec![MockStrategy::Expansion1],
+            vec![MockStrategy::Expansion2],
+        ];
+        let mut queue = ClassQueue::new(pack, 0);
+        queue.add(1);
+        queue.next(None).unwrap();
+        let mut wps = vec![];
+        while let Some(wp) = queue.next(Some(false)) {
+            wps.push((wp.class_label, wp.factory_index));
+        }
+        assert_eq!(wps.len(), 15);
+        assert_eq!(wps[11..], [(0, 6), (1, 6), (0, 7), (1, 7)]);
+    }
+
+    #[test]
+    fn later_tier_after_new_class_test() {
+        let mut pack = pack();
+        pack.expansions = vec![
+            vec![MockStrategy::Expansion1],
+            vec![MockStrategy::Expansion2],
+        ];
+        let mut queue = ClassQueue::new(pack, 0);
+        queue.next(None).unwrap();
+        for _ in 0..6 {
+            queue.next(Some(false)).unwrap();
+        }
+        // The second tier is not applied to class 0 before class 1 has used the first.
+        queue.add(1);
+        let mut wps = vec![];
+        while let Some(wp) = queue.next(Some(false)) {
+            wps.push((wp.class_label, wp.factory_index));
+        }
+        assert_eq!(wps[wps.len() - 3..], [(1, 6), (0, 7), (1, 7)]);
+    }
 }
@@ -1,12 +1,13 @@
 use super::classdb;
-use crate::errors::SpecificationNotFoundError;
+use crate::errors::SearchError;
 use crate::pack::Rule;
 use crate::pack::Strategy;
 use crate::specification::CombinatorialSpecification;
 
 mod simple;
-pub use simple::SimpleRuleDB;
-mod forest;
+pub use simple::{RuleSelection, SimpleRuleDB, StrategyPreference};
+pub mod cross_check;
+pub mod forest;
 pub use forest::ForestRuleDB;
 
 pub trait RuleDB<S: Strategy> {
@@ -14,12 +15,12 @@ pub trait RuleDB<S: Strategy> {
         &mut self,
         root: usize,
         classdb: &classdb::ClassDB<S::ClassType>,
-    ) -> Result<CombinatorialSpecification<S>, SpecificationNotFoundError>;
+    ) -> Result<CombinatorialSpecification<S>, SearchError>;
 
     fn add(&mut self, start: usize, ends: Vec<usize>, rule: Rule<S>);
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
 pub struct RuleLabel {
     parent: usize,
     children: Vec<usize>,
@@ -0,0 +1,508 @@
+//! Consistency checker between the `SimpleRuleDB` and the `ForestRuleDB`.
+//!
+//! Random universes of rules between labels are fed to both databases and the
+//! answers for the root class are compared. The shifts of the structural rules
+//! are non-negative by default, and both databases should then find a
+//! specification for exactly the same roots. Any disagreement is then a bug in one
+//! of the backends and can be shrunk to a minimal set of rules.
+use super::{ForestRuleDB, RuleDB, RuleLabel, SimpleRuleDB};
+use crate::combinatorial_class::CombinatorialClass;
+use crate::pack::{Rule, Strategy};
+use crate::searcher::classdb::ClassDB;
+use crate::specification::CombinatorialSpecification;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+/// Class of a random universe, identified by its label.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LabelClass(pub usize);
+
+impl CombinatorialClass for LabelClass {}
+
+/// A rule of a random universe.
+///
+/// Equivalence rules are two-way, as the `SimpleRuleDB` expects, so they stand
+/// for a pair of rules, one in each direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelRule {
+    pub parent: usize,
+    pub children: Vec<usize>,
+    pub shifts: Vec<i32>,
+    pub equivalence: bool,
+}
+
+impl LabelRule {
+    /// Return the rules fed to the databases for this rule.
+    pub fn directed_rules(&self) -> Vec<LabelRule> {
+        let mut rules = vec![self.clone()];
+        if self.equivalence && self.children[0] != self.parent {
+            rules.push(LabelRule {
+                parent: self.children[0],
+                children: vec![self.parent],
+                shifts: vec![0],
+                equivalence: true,
+            });
+        }
+        rules
+    }
+
+    fn label(&self) -> RuleLabel {
+        RuleLabel::new(self.parent, self.children.clone())
+    }
+
+    pub fn to_rule(&self) -> Rule<LabelStrategy> {
+        let strategy = LabelStrategy {
+            children: self.children.clone(),
+            shifts: self.shifts.clone(),
+            equivalence: self.equivalence,
+        };
+        Rule::new(LabelClass(self.parent), strategy)
+    }
+}
+
+/// Strategy that decomposes a class into the children of a `LabelRule`.
+#[derive(Debug, Clone)]
+pub struct LabelStrategy {
+    children: Vec<usize>,
+    shifts: Vec<i32>,
+    equivalence: bool,
+}
+
+impl Strategy for LabelStrategy {
+    type ClassType = LabelClass;
+
+    fn decompose(&self, _comb_class: &LabelClass) -> Vec<LabelClass> {
+        self.children.iter().map(|&c| LabelClass(c)).collect()
+    }
+
+    fn is_equivalence(&self) -> bool {
+        self.equivalence
+    }
+
+    fn shifts(&self, _comb_class: &LabelClass) -> Vec<i32> {
+        self.shifts.clone()
+    }
+
+    fn is_reversible(&self) -> bool {
+        false
+    }
+}
+
+/// Parameters of the random universes.
+#[derive(Debug, Clone)]
+pub struct UniverseConfig {
+    pub num_classes: usize,
+    pub num_rules: usize,
+    pub max_children: usize,
+    /// Shifts of the structural rules are drawn between `min_shift` and `max_shift`.
+    pub min_shift: i32,
+    pub max_shift: i32,
+    /// Probability that a rule is an equivalence rule with a single child.
+    pub equivalence_probability: f64,
+}
+
+impl UniverseConfig {
+    pub fn new() -> Self {
+        Self {
+            num_classes: 6,
+            num_rules: 10,
+            max_children: 3,
+            min_shift: 1,
+            max_shift: 2,
+            equivalence_probability: 0.0,
+        }
+    }
+}
+
+impl Default for UniverseConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generate a random universe. No two rules have the same label.
+pub fn random_universe(rng: &mut StdRng, config: &UniverseConfig) -> Vec<LabelRule> {
+    let mut labels = HashSet::new();
+    let mut rules = vec![];
+    for _ in 0..config.num_rules {
+        let parent = rng.gen_range(0..config.num_classes);
+        let rule = if rng.gen_bool(config.equivalence_probability) {
+            LabelRule {
+                parent,
+                children: vec![rng.gen_range(0..config.num_classes)],
+                shifts: vec![0],
+                equivalence: true,
+            }
+        } else {
+            let num_children = rng.gen_range(0..=config.max_children);
+            let children: Vec<_> = (0..num_children)
+                .map(|_| rng.gen_range(0..config.num_classes))
+                .collect();
+            let shifts = children
+                .iter()
+                .map(|_| rng.gen_range(config.min_shift..=config.max_shift))
+                .collect();
+            LabelRule {
+                parent,
+                children,
+                shifts,
+                equivalence: false,
+            }
+        };
+        if rule
+            .directed_rules()
+            .iter()
+            .all(|r| labels.insert(r.label()))
+        {
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+/// The ways the two databases can disagree on a universe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disagreement {
+    /// Only the `SimpleRuleDB` finds a specification.
+    OnlySimple,
+    /// Only the `ForestRuleDB` finds a specification.
+    OnlyForest,
+    /// The specification of the `SimpleRuleDB` is not a valid proof tree.
+    InvalidSimple,
+    /// The specification of the `ForestRuleDB` is not a valid proof tree.
+    InvalidForest,
+}
+
+/// A universe on which the two databases disagree for the root class 0.
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+    pub rules: Vec<LabelRule>,
+    pub disagreement: Disagreement,
+}
+
+/// Feed the universe to both databases and compare their answers for the root.
+pub fn cross_check(rules: &[LabelRule], root: usize) -> Result<(), Disagreement> {
+    let classdb = label_classdb(rules, root);
+    let mut simple = SimpleRuleDB::with_seed(0);
+    let mut forest = ForestRuleDB::new();
+    for rule in rules.iter().flat_map(|r| r.directed_rules()) {
+        simple.add(rule.parent, rule.children.clone(), rule.to_rule());
+        forest.add(rule.parent, rule.children.clone(), rule.to_rule());
+    }
+    let simple_spec = simple.get_specification(root, &classdb).ok();
+    let forest_spec = forest.get_specification(root, &classdb).ok();
+    match (simple_spec, forest_spec) {
+        (None, None) => Ok(()),
+        (Some(_), None) => Err(Disagreement::OnlySimple),
+        (None, Some(_)) => Err(Disagreement::OnlyForest),
+        (Some(simple_spec), Some(forest_spec)) => {
+            if !is_valid_proof_tree(&simple_spec, rules, root) {
+                Err(Disagreement::InvalidSimple)
+            } else if !is_valid_proof_tree(&forest_spec, rules, root) {
+                Err(Disagreement::InvalidForest)
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Return a class database where the label of each class is its own label.
+pub(crate) fn label_classdb(rules: &[LabelRule], root: usize) -> ClassDB<LabelClass> {
+    let mut classdb = ClassDB::new();
+    let num_classes = rules
+        .iter()
+        .flat_map(|r| r.children.iter().chain([&r.parent]))
+        .max()
+        .map_or(0, |&m| m + 1)
+        .max(root + 1);
+    for label in 0..num_classes {
+        classdb
+            .get_label_from_class_or_add(&LabelClass(label))
+            .unwrap();
+    }
+    classdb
+}
+
+/// Check that the specification uses rules of the universe, has exactly one rule
+/// for each class it uses and can count the root at every size.
+///
+/// The last check is done on the rules directly rather than with the table
+/// method that the harness tests: no rule shifts a child by a negative amount
+/// and the children that are not shifted never lead back to the parent. A
+/// specification with a negative shift is rejected even if the table method
+/// could count it.
+pub(crate) fn is_valid_proof_tree(
+    spec: &CombinatorialSpecification<LabelStrategy>,
+    rules: &[LabelRule],
+    root: usize,
+) -> bool {
+    let universe: HashSet<_> = rules
+        .iter()
+        .flat_map(|r| r.directed_rules())
+        .map(|r| r.label())
+        .collect();
+    let mut unshifted_children = HashMap::new();
+    for rule in spec.rules.iter() {
+        let LabelClass(parent) = *rule.get_parent();
+        let children: Vec<_> = rule.get_children().iter().map(|c| c.0).collect();
+        let shifts = rule.get_shifts();
+        if !universe.contains(&RuleLabel::new(parent, children.clone()))
+            || shifts.iter().any(|&s| s < 0)
+        {
+            return false;
+        }
+        let unshifted: Vec<_> = children
+            .into_iter()
+            .zip(shifts)
+            .filter(|&(_, s)| s == 0)
+            .map(|(c, _)| c)
+            .collect();
+        if unshifted_children.insert(parent, unshifted).is_some() {
+            return false;
+        }
+    }
+    let closed = spec
+        .rules
+        .iter()
+        .flat_map(|r| r.get_children())
+        .all(|c| unshifted_children.contains_key(&c.0));
+    if spec.root != LabelClass(root) || !unshifted_children.contains_key(&root) || !closed {
+        return false;
+    }
+    // Remove the classes whose children that are not shifted are all removed. A
+    // class is left exactly when it lies on or leads to a cycle without shift.
+    let mut removed = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (&parent, unshifted) in unshifted_children.iter() {
+            if !removed.contains(&parent) && unshifted.iter().all(|c| removed.contains(c)) {
+                removed.insert(parent);
+                changed = true;
+            }
+        }
+    }
+    removed.len() == unshifted_children.len()
+}
+
+/// Remove rules from the universe as long as the same disagreement remains.
+///
+/// The returned universe is minimal at the granularity of rules: removing any
+/// single rule makes the disagreement disappear. The children and shifts of the
+/// remaining rules are not shrunk.
+pub fn shrink(counterexample: Counterexample, root: usize) -> Counterexample {
+    let Counterexample {
+        mut rules,
+        disagreement,
+    } = counterexample;
+    let mut idx = 0;
+    while idx < rules.len() {
+        let mut smaller = rules.clone();
+        smaller.remove(idx);
+        if cross_check(&smaller, root) == Err(disagreement) {
+            rules = smaller;
+            idx = 0;
+        } else {
+            idx += 1;
+        }
+    }
+    Counterexample {
+        rules,
+        disagreement,
+    }
+}
+
+/// Cross check the given number of random universes, with the class 0 as root,
+/// and return the first disagreement found after shrinking it.
+pub fn find_counterexample(
+    seed: u64,
+    num_universes: usize,
+    config: &UniverseConfig,
+) -> Option<Counterexample> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..num_universes {
+        let rules = random_universe(&mut rng, config);
+        if let Err(disagreement) = cross_check(&rules, 0) {
+            return Some(shrink(
+                Counterexample {
+                    rules,
+                    disagreement,
+                },
+                0,
+            ));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(parent: usize, children: Vec<usize>, shift: i32) -> LabelRule {
+        LabelRule {
+            parent,
+            shifts: vec![shift; children.len()],
+            children,
+            equivalence: false,
+        }
+    }
+
+    fn specification(rules: &[LabelRule]) -> CombinatorialSpecification<LabelStrategy> {
+        CombinatorialSpecification {
+            rules: rules.iter().map(|r| r.to_rule()).collect(),
+            root: LabelClass(0),
+        }
+    }
+
+    /// The well-foundedness of a specification is checked without the table
+    /// method.
+    #[test]
+    fn valid_proof_tree_test() {
+        let shifted_cycle = [rule(0, vec![1], 1), rule(1, vec![0], 0)];
+        assert!(is_valid_proof_tree(
+            &specification(&shifted_cycle),
+            &shifted_cycle,
+            0
+        ));
+        let zero_shift_cycle = [rule(0, vec![1], 0), rule(1, vec![0], 0)];
+        assert!(!is_valid_proof_tree(
+            &specification(&zero_shift_cycle),
+            &zero_shift_cycle,
+            0
+        ));
+        let negative_shift = [rule(0, vec![1], -1), rule(1, vec![], 0)];
+        assert!(!is_valid_proof_tree(
+            &specification(&negative_shift),
+            &negative_shift,
+            0
+        ));
+        let two_rules = [rule(0, vec![], 0), rule(0, vec![1], 1), rule(1, vec![], 0)];
+        assert!(!is_valid_proof_tree(
+            &specification(&two_rules),
+            &two_rules,
+            0
+        ));
+    }
+
+    #[test]
+    fn agreement_on_productive_universes_test() {
+        let config = UniverseConfig::new();
+        let counterexample = find_counterexample(0, 300, &config);
+        assert!(counterexample.is_none(), "{:?}", counterexample);
+    }
+
+    #[test]
+    fn agreement_on_tree_test() {
+        let rules = vec![
+            LabelRule {
+                parent: 0,
+                children: vec![1, 2],
+                shifts: vec![1, 1],
+                equivalence: false,
+            },
+            LabelRule {
+                parent: 1,
+                children: vec![],
+                shifts: vec![],
+                equivalence: false,
+            },
+            LabelRule {
+                parent: 2,
+                children: vec![0],
+                shifts: vec![1],
+                equivalence: false,
+            },
+        ];
+        assert_eq!(cross_check(&rules, 0), Ok(()));
+        assert_eq!(cross_check(&rules[..2], 0), Ok(()));
+    }
+
+    /// The root is only equivalent to a verified class.
+    #[test]
+    fn agreement_on_equivalent_root_test() {
+        let rules = vec![
+            LabelRule {
+                parent: 3,
+                children: vec![],
+                shifts: vec![],
+                equivalence: false,
+            },
+            LabelRule {
+                parent: 0,
+                children: vec![3],
+                shifts: vec![0],
+                equivalence: true,
+            },
+        ];
+        assert_eq!(cross_check(&rules, 0), Ok(()));
+    }
+
+    #[test]
+    fn agreement_with_equivalences_test() {
+        let config = UniverseConfig {
+            equivalence_probability: 0.5,
+            ..UniverseConfig::new()
+        };
+        let counterexample = find_counterexample(0, 300, &config);
+        assert!(counterexample.is_none(), "{:?}", counterexample);
+    }
+
+    /// A cycle of structural rules that do not shift the size can not count
+    /// anything, so neither database uses it.
+    #[test]
+    fn agreement_with_zero_shifts_test() {
+        assert_eq!(
+            cross_check(&[rule(0, vec![1], 0), rule(1, vec![0], 0)], 0),
+            Ok(())
+        );
+        let config = UniverseConfig {
+            min_shift: 0,
+            equivalence_probability: 0.3,
+            ..UniverseConfig::new()
+        };
+        let counterexample = find_counterexample(0, 300, &config);
+        assert!(counterexample.is_none(), "{:?}", counterexample);
+    }
+
+    /// The `SimpleRuleDB` never uses a rule with a negative shift.
+    #[test]
+    fn negative_shift_counterexample_test() {
+        let config = UniverseConfig {
+            min_shift: -1,
+            ..UniverseConfig::new()
+        };
+        let counterexample = find_counterexample(0, 300, &config).unwrap();
+        assert_eq!(counterexample.disagreement, Disagreement::OnlyForest);
+        assert!(counterexample
+            .rules
+            .iter()
+            .any(|r| r.shifts.iter().any(|&s| s < 0)));
+    }
+
+    #[test]
+    fn shrink_test() {
+        let rules = vec![
+            rule(2, vec![], 0),
+            rule(0, vec![1], -1),
+            rule(3, vec![2, 2], 1),
+            rule(1, vec![0, 1], 0),
+            rule(1, vec![], 0),
+            rule(4, vec![3], 1),
+        ];
+        assert_eq!(cross_check(&rules, 0), Err(Disagreement::OnlyForest));
+        let counterexample = shrink(
+            Counterexample {
+                rules,
+                disagreement: Disagreement::OnlyForest,
+            },
+            0,
+        );
+        assert_eq!(
+            counterexample.rules,
+            vec![rule(0, vec![1], -1), rule(1, vec![], 0)]
+        );
+    }
+}
@@ -1,30 +1,733 @@
-use super::{Rule, RuleDB, Strategy};
-use crate::errors::SpecificationNotFoundError;
+use super::{Rule, RuleDB, RuleLabel, Strategy};
+use crate::errors::{InvalidMinimizeOrderError, SearchError, SpecificationNotFoundError};
 use crate::searcher::classdb;
 use crate::CombinatorialSpecification;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
 
 mod extractor;
 mod function;
 mod table_method;
 
+pub use extractor::DEFAULT_MINIMIZE_ORDER;
 pub use function::{Function, IntOrInf};
 pub use table_method::{ForestRuleKey, RuleBucket, TableMethod};
 
-pub struct ForestRuleDB {
+/// Cost of a strategy used when extracting a specification.
+pub type StrategyCost<S> = Box<dyn Fn(&S) -> u64>;
+
+/// Rule database that finds specification using the table method.
+///
+/// Contrary to the `SimpleRuleDB`, a specification is only returned once the root
+/// class is pumping, i.e. the rules can count it at every size.
+pub struct ForestRuleDB<S: Strategy> {
     reverse: bool,
     table_method: TableMethod,
+    rule_to_strategy: HashMap<RuleLabel, S>,
+    /// The strategy each reverse rule was built from. The first strategy of the
+    /// original rule may not be reversible.
+    reverse_rules: HashMap<RuleLabel, S>,
+    minimize_order: Vec<RuleBucket>,
+    strategy_cost: Option<StrategyCost<S>>,
+}
+
+impl<S: Strategy> ForestRuleDB<S> {
+    pub fn new() -> Self {
+        Self {
+            reverse: false,
+            table_method: TableMethod::new(),
+            rule_to_strategy: HashMap::new(),
+            reverse_rules: HashMap::new(),
+            minimize_order: DEFAULT_MINIMIZE_ORDER.to_vec(),
+            strategy_cost: None,
+        }
+    }
+
+    /// Create a database that also adds the reverse rules of every reversible
+    /// rule.
+    pub fn new_with_reverse() -> Self {
+        Self {
+            reverse: true,
+            ..Self::new()
+        }
+    }
+
+    /// Set the order in which the buckets of rules are minimized when extracting
+    /// a specification.
+    ///
+    /// The order must contain each bucket of `DEFAULT_MINIMIZE_ORDER` exactly once,
+    /// otherwise a class could keep several rules.
+    pub fn set_minimize_order(
+        &mut self,
+        order: Vec<RuleBucket>,
+    ) -> Result<(), InvalidMinimizeOrderError> {
+        if !extractor::is_valid_order(&order) {
+            return Err(InvalidMinimizeOrderError {});
+        }
+        self.minimize_order = order;
+        Ok(())
+    }
+
+    /// Set the cost of the strategies. The extracted specifications greedily
+    /// minimize the total cost of their rules.
+    pub fn set_strategy_cost(&mut self, cost: impl Fn(&S) -> u64 + 'static) {
+        self.strategy_cost = Some(Box::new(cost));
+    }
+
+    /// Iterate over at most `max_count` distinct minimal specifications of the root
+    /// class.
+    ///
+    /// Each specification is found by minimizing the pumping subuniverse with some
+    /// of its rules forced in, which costs one removal per rule. At most
+    /// `max_explored` such minimizations are done, even if they give fewer than
+    /// `max_count` distinct specifications.
+    pub fn iter_specifications<'a>(
+        &'a self,
+        root: usize,
+        classdb: &'a classdb::ClassDB<S::ClassType>,
+        max_count: usize,
+        max_explored: usize,
+    ) -> impl Iterator<Item = io::Result<CombinatorialSpecification<S>>> + 'a {
+        let rules: Vec<_> = self.table_method.pumping_subuniverse().cloned().collect();
+        let config = self.extractor_config(rules.iter());
+        extractor::SpecificationIterator::new(
+            forest_label(root),
+            rules,
+            config,
+            max_count,
+            max_explored,
+        )
+        .map(move |rule_keys| self.to_specification(root, rule_keys, classdb))
+    }
+
+    /// Return the number of terms of the class that can be counted with the rules
+    /// added so far.
+    pub fn computable_terms(&self, label: usize) -> IntOrInf {
+        self.table_method.get_value(forest_label(label)).clone()
+    }
+
+    /// Return a specification that counts the root class correctly for the first
+    /// `computable_terms(root)` sizes, even if the root class is not pumping yet.
+    ///
+    /// Classes that are not needed to count those sizes may have no rule in the
+    /// specification. If the root class is pumping, this is the same as
+    /// `get_specification`.
+    pub fn get_partial_specification(
+        &mut self,
+        root: usize,
+        classdb: &classdb::ClassDB<S::ClassType>,
+    ) -> Result<CombinatorialSpecification<S>, SearchError> {
+        let terms = match self.computable_terms(root) {
+            IntOrInf::Infinity => return self.get_specification(root, classdb),
+            IntOrInf::Int(0) => return Err(SpecificationNotFoundError {}.into()),
+            IntOrInf::Int(terms) => terms,
+        };
+        let mut tb = TableMethod::new();
+        for rule_key in self.table_method.rules() {
+            tb.add_rule_key(rule_key.clone());
+        }
+        let config = self.extractor_config(self.table_method.rules());
+        let rule_keys =
+            extractor::extract_partial_specification(forest_label(root), terms, tb, &config);
+        Ok(self.to_specification(root, rule_keys, classdb)?)
+    }
+
+    /// Build the specification of the root from the forest rule keys of its rules.
+    fn to_specification(
+        &self,
+        root: usize,
+        rule_keys: Vec<ForestRuleKey>,
+        classdb: &classdb::ClassDB<S::ClassType>,
+    ) -> io::Result<CombinatorialSpecification<S>> {
+        let actual_rules = rule_keys
+            .iter()
+            .map(|rule_key| self.get_rule(rule_key, classdb))
+            .collect::<io::Result<Vec<Rule<S>>>>()?;
+        Ok(CombinatorialSpecification {
+            rules: actual_rules,
+            root: classdb.get_class_from_label(root)?.unwrap(),
+        })
+    }
+
+    /// Add the reverse rules computing each child of the rule.
+    fn add_reverse_rules(&mut self, start: usize, ends: &[usize], rule: &Rule<S>) {
+        for (idx, &end) in ends.iter().enumerate() {
+            let mut reverse_ends = vec![start];
+            reverse_ends.extend(
+                ends.iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != idx)
+                    .map(|(_, &e)| e),
+            );
+            let reverse_rule = rule.to_reverse_rule(idx);
+            let rule_key = Self::forest_rule_key(end, &reverse_ends, &reverse_rule);
+            if let Entry::Vacant(entry) =
+                self.reverse_rules.entry(RuleLabel::new(end, reverse_ends))
+            {
+                self.table_method.add_rule_key(rule_key);
+                entry.insert(reverse_rule.get_strategy());
+            }
+        }
+    }
+
+    /// Build the configuration of the extractor for the given rules.
+    fn extractor_config<'a>(
+        &self,
+        rule_keys: impl Iterator<Item = &'a ForestRuleKey>,
+    ) -> extractor::ExtractorConfig {
+        let config = extractor::ExtractorConfig::new().with_order(self.minimize_order.clone());
+        match &self.strategy_cost {
+            None => config,
+            Some(strategy_cost) => {
+                let costs: HashMap<_, _> = rule_keys
+                    .map(|rk| (rk.clone(), strategy_cost(self.get_strategy(rk))))
+                    .collect();
+                config.with_cost(move |rk| costs[rk])
+            }
+        }
+    }
+
+    /// Return the strategy of the rule corresponding to the forest rule key.
+    ///
+    /// For a reverse rule, this is the strategy of the original rule it was built
+    /// from.
+    fn get_strategy(&self, rule_key: &ForestRuleKey) -> &S {
+        let parent = *rule_key.get_parent() as usize;
+        let children: Vec<_> = rule_key.iter_children().map(|&c| c as usize).collect();
+        let label = RuleLabel::new(parent, children);
+        if *rule_key.get_bucket() == RuleBucket::Reverse {
+            &self.reverse_rules[&label]
+        } else {
+            &self.rule_to_strategy[&label]
+        }
+    }
+
+    /// Rebuild the rule of the class database corresponding to the forest rule key.
+    fn get_rule(
+        &self,
+        rule_key: &ForestRuleKey,
+        classdb: &classdb::ClassDB<S::ClassType>,
+    ) -> io::Result<Rule<S>> {
+        let parent = *rule_key.get_parent() as usize;
+        let strategy = self.get_strategy(rule_key).clone();
+        if *rule_key.get_bucket() != RuleBucket::Reverse {
+            let parent_class = classdb.get_class_from_label(parent)?.unwrap();
+            return Ok(Rule::new(parent_class, strategy));
+        }
+        let original_parent = *rule_key.iter_children().next().unwrap() as usize;
+        let original_parent_class = classdb.get_class_from_label(original_parent)?.unwrap();
+        let original_rule = Rule::new(original_parent_class, strategy);
+        let parent_class = classdb.get_class_from_label(parent)?.unwrap();
+        let idx = original_rule
+            .get_children()
+            .iter()
+            .position(|c| *c == parent_class)
+            .unwrap();
+        Ok(original_rule.to_reverse_rule(idx))
+    }
+
+    /// Build the forest rule key for a rule between the given labels.
+    fn forest_rule_key(start: usize, ends: &[usize], rule: &Rule<S>) -> ForestRuleKey {
+        ForestRuleKey::from_rule(
+            forest_label(start),
+            ends.iter().map(|&e| forest_label(e)).collect(),
+            rule,
+        )
+    }
 }
 
-impl<S: Strategy> RuleDB<S> for ForestRuleDB {
+impl<S: Strategy> Default for ForestRuleDB<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Strategy> RuleDB<S> for ForestRuleDB<S> {
     fn add(&mut self, start: usize, ends: Vec<usize>, rule: Rule<S>) {
-        unimplemented!()
+        if self.reverse && rule.is_reversible() {
+            self.add_reverse_rules(start, &ends, &rule);
+        }
+        let rule_key = Self::forest_rule_key(start, &ends, &rule);
+        // The table only keeps the first rule with the label, so the strategy must
+        // be the one whose shifts it was given.
+        if let Entry::Vacant(entry) = self.rule_to_strategy.entry(RuleLabel::new(start, ends)) {
+            self.table_method.add_rule_key(rule_key);
+            entry.insert(rule.get_strategy());
+        }
     }
 
     fn get_specification(
         &mut self,
         root: usize,
         classdb: &classdb::ClassDB<S::ClassType>,
-    ) -> Result<CombinatorialSpecification<S>, SpecificationNotFoundError> {
-        unimplemented!()
+    ) -> Result<CombinatorialSpecification<S>, SearchError> {
+        let root_label = forest_label(root);
+        if !self.table_method.is_pumping(root_label) {
+            return Err(SpecificationNotFoundError {}.into());
+        }
+        let mut tb = TableMethod::new();
+        for rule_key in self.table_method.pumping_subuniverse() {
+            tb.add_rule_key(rule_key.clone());
+        }
+        let config = self.extractor_config(self.table_method.pumping_subuniverse());
+        let rule_keys = extractor::extract_specification(root_label, tb, &config);
+        Ok(self.to_specification(root, rule_keys, classdb)?)
+    }
+}
+
+/// Convert a label of the class database to a label of the table method.
+fn forest_label(label: usize) -> u32 {
+    u32::try_from(label).expect("Class label too large for the forest")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::StrategyPack;
+    use crate::searcher::ruledb::cross_check::{LabelClass, LabelRule};
+    use crate::word::{AvoidingWithPrefix, WordStrategy};
+    use crate::CombinatorialSpecificationSearcher;
+
+    fn class(prefix: &str, just_prefix: bool) -> AvoidingWithPrefix {
+        let patterns = vec![String::from("a"), String::from("b")];
+        let alphabet = vec!['a', 'b'];
+        if just_prefix {
+            AvoidingWithPrefix::new_just_prefix(String::from(prefix), patterns, alphabet)
+        } else {
+            AvoidingWithPrefix::new(String::from(prefix), patterns, alphabet)
+        }
+    }
+
+    #[test]
+    fn no_specification_until_pumping_test() {
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb
+            .get_label_from_class_or_add(&class("b", false))
+            .unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        assert!(ruledb.get_specification(root, &classdb).is_err());
+        ruledb.add(
+            root,
+            vec![],
+            Rule::new(class("b", false), WordStrategy::Empty),
+        );
+        let spec = ruledb.get_specification(root, &classdb).unwrap();
+        assert_eq!(spec.rules.len(), 1);
+        assert_eq!(spec.root, class("b", false));
+    }
+
+    #[test]
+    fn extract_tree_specification_test() {
+        let mut classdb = classdb::ClassDB::new();
+        let mut ruledb = ForestRuleDB::new();
+        let root = classdb
+            .get_label_from_class_or_add(&class("", false))
+            .unwrap();
+        let rule = Rule::new(class("", false), WordStrategy::Expansion);
+        let ends: Vec<_> = rule
+            .get_children()
+            .iter()
+            .map(|c| classdb.get_label_from_class_or_add(c).unwrap())
+            .collect();
+        ruledb.add(root, ends.clone(), rule);
+        for (end, child) in ends
+            .iter()
+            .zip([class("", true), class("a", false), class("b", false)])
+        {
+            let strategy = if child.is_emtpy() {
+                WordStrategy::Empty
+            } else {
+                WordStrategy::Atom
+            };
+            assert!(ruledb.get_specification(root, &classdb).is_err());
+            ruledb.add(*end, vec![], Rule::new(child, strategy));
+        }
+        let spec = ruledb.get_specification(root, &classdb).unwrap();
+        assert_eq!(spec.rules.len(), 4);
+    }
+
+    #[test]
+    fn auto_search_test() {
+        let start_class = AvoidingWithPrefix::new(
+            String::from(""),
+            vec![String::from("ababa"), String::from("babb")],
+            vec!['a', 'b'],
+        );
+        for ruledb in [ForestRuleDB::new(), ForestRuleDB::new_with_reverse()] {
+            let pack = StrategyPack {
+                initials: vec![WordStrategy::RemoveFrontOfPrefix],
+                inferrals: vec![],
+                expansions: vec![vec![WordStrategy::Expansion]],
+                verifications: vec![WordStrategy::Empty, WordStrategy::Atom],
+            };
+            let mut searcher =
+                CombinatorialSpecificationSearcher::new(start_class.clone(), pack, ruledb);
+            let spec = searcher.auto_search().unwrap();
+            assert_eq!(spec.root, start_class);
+            let parents: Vec<_> = spec.rules.iter().map(|r| r.get_parent()).collect();
+            assert!(parents.contains(&&start_class));
+            for rule in spec.rules.iter() {
+                for child in rule.get_children() {
+                    assert!(parents.contains(&child));
+                }
+            }
+        }
+    }
+
+    /// The class with prefix "ba" has no rule of its own and can only be counted
+    /// with the reverse of the expansion of "b".
+    #[test]
+    fn reverse_rule_test() {
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let word = |prefix: &str| {
+            AvoidingWithPrefix::new(String::from(prefix), patterns.clone(), alphabet.clone())
+        };
+        let just = |prefix: &str| {
+            AvoidingWithPrefix::new_just_prefix(
+                String::from(prefix),
+                patterns.clone(),
+                alphabet.clone(),
+            )
+        };
+        let rules = || {
+            vec![
+                Rule::new(word(""), WordStrategy::Expansion),
+                Rule::new(word("a"), WordStrategy::Expansion),
+                Rule::new(word("aa"), WordStrategy::Empty),
+                Rule::new(word("ab"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(word("b"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(word("b"), WordStrategy::Expansion),
+                Rule::new(word("bb"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(just(""), WordStrategy::Atom),
+                Rule::new(just("a"), WordStrategy::Atom),
+                Rule::new(just("ab"), WordStrategy::Atom),
+                Rule::new(just("b"), WordStrategy::Atom),
+                Rule::new(just("bb"), WordStrategy::Atom),
+            ]
+        };
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb.get_label_from_class_or_add(&word("ba")).unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        let mut reverse_ruledb = ForestRuleDB::new_with_reverse();
+        for db in [&mut ruledb, &mut reverse_ruledb] {
+            for rule in rules() {
+                let start = classdb
+                    .get_label_from_class_or_add(rule.get_parent())
+                    .unwrap();
+                let ends: Vec<_> = rule
+                    .get_children()
+                    .iter()
+                    .map(|c| classdb.get_label_from_class_or_add(c).unwrap())
+                    .collect();
+                db.add(start, ends, rule);
+            }
+        }
+        assert!(ruledb.get_specification(root, &classdb).is_err());
+        let spec = reverse_ruledb.get_specification(root, &classdb).unwrap();
+        let reverse_rules: Vec<_> = spec.rules.iter().filter(|r| r.is_reverse()).collect();
+        assert_eq!(reverse_rules.len(), 1);
+        assert_eq!(*reverse_rules[0].get_parent(), word("ba"));
+        assert_eq!(
+            *reverse_rules[0].get_children(),
+            vec![word("b"), just("b"), word("bb")]
+        );
+        assert_eq!(spec.rules.len(), 12);
+    }
+
+    /// The class with prefix "ab" has no rule, so the empty prefix can not be
+    /// counted and "bbb" can only be counted for the sizes that do not need it.
+    #[test]
+    fn partial_specification_test() {
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let word = |prefix: &str| {
+            AvoidingWithPrefix::new(String::from(prefix), patterns.clone(), alphabet.clone())
+        };
+        let just = |prefix: &str| {
+            AvoidingWithPrefix::new_just_prefix(
+                String::from(prefix),
+                patterns.clone(),
+                alphabet.clone(),
+            )
+        };
+        let rules = vec![
+            Rule::new(word("bbb"), WordStrategy::RemoveFrontOfPrefix),
+            Rule::new(word(""), WordStrategy::Expansion),
+            Rule::new(word("a"), WordStrategy::Expansion),
+            Rule::new(word("aa"), WordStrategy::Empty),
+            Rule::new(just("bbb"), WordStrategy::Atom),
+            Rule::new(just(""), WordStrategy::Atom),
+            Rule::new(just("a"), WordStrategy::Atom),
+        ];
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb.get_label_from_class_or_add(&word("bbb")).unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        for rule in rules {
+            let start = classdb
+                .get_label_from_class_or_add(rule.get_parent())
+                .unwrap();
+            let ends: Vec<_> = rule
+                .get_children()
+                .iter()
+                .map(|c| classdb.get_label_from_class_or_add(c).unwrap())
+                .collect();
+            ruledb.add(start, ends, rule);
+        }
+        let empty = classdb.get_label_from_class_or_add(&word("")).unwrap();
+        let ab = classdb.get_label_from_class_or_add(&word("ab")).unwrap();
+        assert_eq!(ruledb.computable_terms(root), IntOrInf::Int(3));
+        assert_eq!(ruledb.computable_terms(empty), IntOrInf::Int(0));
+        assert_eq!(ruledb.computable_terms(ab), IntOrInf::Int(0));
+        assert!(ruledb.get_specification(root, &classdb).is_err());
+        assert!(ruledb.get_partial_specification(empty, &classdb).is_err());
+        let spec = ruledb.get_partial_specification(root, &classdb).unwrap();
+        assert_eq!(spec.root, word("bbb"));
+        let parents: Vec<_> = spec.rules.iter().map(|r| r.get_parent()).collect();
+        assert_eq!(parents.len(), 2);
+        assert!(parents.contains(&&word("bbb")));
+        assert!(parents.contains(&&just("bbb")));
+    }
+
+    #[test]
+    fn partial_specification_of_pumping_class_test() {
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb
+            .get_label_from_class_or_add(&class("b", false))
+            .unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        ruledb.add(
+            root,
+            vec![],
+            Rule::new(class("b", false), WordStrategy::Empty),
+        );
+        assert_eq!(ruledb.computable_terms(root), IntOrInf::Infinity);
+        let spec = ruledb.get_partial_specification(root, &classdb).unwrap();
+        assert_eq!(spec.rules.len(), 1);
+    }
+
+    #[test]
+    fn strategy_cost_test() {
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let word = |prefix: &str| {
+            AvoidingWithPrefix::new(String::from(prefix), patterns.clone(), alphabet.clone())
+        };
+        let just = |prefix: &str| {
+            AvoidingWithPrefix::new_just_prefix(
+                String::from(prefix),
+                patterns.clone(),
+                alphabet.clone(),
+            )
+        };
+        let rules = || {
+            vec![
+                Rule::new(word(""), WordStrategy::Expansion),
+                Rule::new(word("a"), WordStrategy::Expansion),
+                Rule::new(word("aa"), WordStrategy::Empty),
+                Rule::new(word("ab"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(word("b"), WordStrategy::Expansion),
+                Rule::new(word("ba"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(word("b"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(word("bb"), WordStrategy::RemoveFrontOfPrefix),
+                Rule::new(just(""), WordStrategy::Atom),
+                Rule::new(just("a"), WordStrategy::Atom),
+                Rule::new(just("ab"), WordStrategy::Atom),
+                Rule::new(just("b"), WordStrategy::Atom),
+                Rule::new(just("bb"), WordStrategy::Atom),
+            ]
+        };
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb.get_label_from_class_or_add(&word("")).unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        ruledb.set_strategy_cost(|s| match s {
+            WordStrategy::Expansion => 10,
+            _ => 1,
+        });
+        for rule in rules() {
+            let start = classdb
+                .get_label_from_class_or_add(rule.get_parent())
+                .unwrap();
+            let ends: Vec<_> = rule
+                .get_children()
+                .iter()
+                .map(|c| classdb.get_label_from_class_or_add(c).unwrap())
+                .collect();
+            ruledb.add(start, ends, rule);
+        }
+        let spec = ruledb.get_specification(root, &classdb).unwrap();
+        let b_rules: Vec<_> = spec
+            .rules
+            .iter()
+            .filter(|r| *r.get_parent() == word("b"))
+            .collect();
+        assert_eq!(b_rules.len(), 1);
+        assert_eq!(*b_rules[0].get_children(), vec![just("b"), word("")]);
+    }
+
+    #[test]
+    fn iter_specifications_test() {
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let word = |prefix: &str| {
+            AvoidingWithPrefix::new(String::from(prefix), patterns.clone(), alphabet.clone())
+        };
+        let just = |prefix: &str| {
+            AvoidingWithPrefix::new_just_prefix(
+                String::from(prefix),
+                patterns.clone(),
+                alphabet.clone(),
+            )
+        };
+        let rules = vec![
+            Rule::new(word(""), WordStrategy::Expansion),
+            Rule::new(word("a"), WordStrategy::Expansion),
+            Rule::new(word("aa"), WordStrategy::Empty),
+            Rule::new(word("ab"), WordStrategy::RemoveFrontOfPrefix),
+            Rule::new(word("b"), WordStrategy::RemoveFrontOfPrefix),
+            Rule::new(word("b"), WordStrategy::Expansion),
+            Rule::new(word("ba"), WordStrategy::RemoveFrontOfPrefix),
+            Rule::new(word("bb"), WordStrategy::RemoveFrontOfPrefix),
+            Rule::new(just(""), WordStrategy::Atom),
+            Rule::new(just("a"), WordStrategy::Atom),
+            Rule::new(just("ab"), WordStrategy::Atom),
+            Rule::new(just("b"), WordStrategy::Atom),
+            Rule::new(just("bb"), WordStrategy::Atom),
+        ];
+        let mut classdb = classdb::ClassDB::new();
+        let root = classdb.get_label_from_class_or_add(&word("")).unwrap();
+        let mut ruledb = ForestRuleDB::new();
+        for rule in rules {
+            let start = classdb
+                .get_label_from_class_or_add(rule.get_parent())
+                .unwrap();
+            let ends: Vec<_> = rule
+                .get_children()
+                .iter()
+                .map(|c| classdb.get_label_from_class_or_add(c).unwrap())
+                .collect();
+            ruledb.add(start, ends, rule);
+        }
+        let specs: Vec<_> = ruledb
+            .iter_specifications(root, &classdb, 10, 100)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(specs.len(), 2);
+        for spec in specs.iter() {
+            assert_eq!(spec.root, word(""));
+            let parents: Vec<_> = spec.rules.iter().map(|r| r.get_parent()).collect();
+            for rule in spec.rules.iter() {
+                for child in rule.get_children() {
+                    assert!(parents.contains(&child));
+                }
+            }
+        }
+        assert_ne!(specs[0].rules.len(), specs[1].rules.len());
+        assert_eq!(
+            ruledb.iter_specifications(root, &classdb, 1, 100).count(),
+            1
+        );
+    }
+
+    /// A second strategy for the same rule does not replace the one whose shifts
+    /// the table method uses.
+    #[test]
+    fn keep_first_strategy_test() {
+        let rule = |parent: usize, children: Vec<usize>, shift: i32| LabelRule {
+            parent,
+            shifts: vec![shift; children.len()],
+            children,
+            equivalence: false,
+        };
+        let mut classdb = classdb::ClassDB::new();
+        for label in 0..2 {
+            classdb
+                .get_label_from_class_or_add(&LabelClass(label))
+                .unwrap();
+        }
+        let mut ruledb = ForestRuleDB::new();
+        for rule in [rule(0, vec![1], 0), rule(0, vec![1], 1), rule(1, vec![], 0)] {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        let spec = ruledb.get_specification(0, &classdb).unwrap();
+        let root_rule = spec
+            .rules
+            .iter()
+            .find(|r| *r.get_parent() == LabelClass(0))
+            .unwrap();
+        assert_eq!(root_rule.get_shifts(), vec![0]);
+    }
+
+    /// Strategy that splits the class 0 into the classes 1 and 2, or that verifies a
+    /// class.
+    #[derive(Debug, Clone)]
+    enum SplitStrategy {
+        Split { reversible: bool },
+        Verification,
+    }
+
+    impl Strategy for SplitStrategy {
+        type ClassType = LabelClass;
+
+        fn decompose(&self, _comb_class: &LabelClass) -> Vec<LabelClass> {
+            match self {
+                SplitStrategy::Split { .. } => vec![LabelClass(1), LabelClass(2)],
+                SplitStrategy::Verification => vec![],
+            }
+        }
+
+        fn is_equivalence(&self) -> bool {
+            false
+        }
+
+        fn shifts(&self, comb_class: &LabelClass) -> Vec<i32> {
+            vec![0; self.decompose(comb_class).len()]
+        }
+
+        fn is_reversible(&self) -> bool {
+            matches!(self, SplitStrategy::Split { reversible: true })
+        }
+    }
+
+    /// The reverse rule of a reversible strategy is rebuilt from that strategy,
+    /// even if a strategy that is not reversible was found first for the rule.
+    #[test]
+    fn reverse_rule_of_second_strategy_test() {
+        let mut classdb = classdb::ClassDB::new();
+        for label in 0..3 {
+            classdb
+                .get_label_from_class_or_add(&LabelClass(label))
+                .unwrap();
+        }
+        let mut ruledb = ForestRuleDB::new_with_reverse();
+        for reversible in [false, true] {
+            let rule = Rule::new(LabelClass(0), SplitStrategy::Split { reversible });
+            ruledb.add(0, vec![1, 2], rule);
+        }
+        for label in [0, 2] {
+            let rule = Rule::new(LabelClass(label), SplitStrategy::Verification);
+            ruledb.add(label, vec![], rule);
+        }
+        let spec = ruledb.get_specification(1, &classdb).unwrap();
+        let reverse_rule = spec.rules.iter().find(|r| r.is_reverse()).unwrap();
+        assert_eq!(*reverse_rule.get_parent(), LabelClass(1));
+        assert_eq!(
+            *reverse_rule.get_children(),
+            vec![LabelClass(0), LabelClass(2)]
+        );
+    }
+
+    #[test]
+    fn invalid_minimize_order_test() {
+        let mut ruledb: ForestRuleDB<WordStrategy> = ForestRuleDB::new();
+        assert!(ruledb.set_minimize_order(vec![]).is_err());
+        assert!(ruledb
+            .set_minimize_order(vec![RuleBucket::Normal, RuleBucket::Verification])
+            .is_err());
+        let mut order = DEFAULT_MINIMIZE_ORDER.to_vec();
+        order.reverse();
+        assert!(ruledb.set_minimize_order(order.clone()).is_ok());
+        assert_eq!(ruledb.minimize_order, order);
     }
 }
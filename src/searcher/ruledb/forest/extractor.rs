@@ -1,77 +1,180 @@
 use super::ForestRuleKey;
+use super::IntOrInf;
 use super::RuleBucket;
 use super::TableMethod;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-const MINIMIZE_ORDER: [RuleBucket; 4] = [
+/// Order in which the buckets are minimized when no other order is given.
+pub const DEFAULT_MINIMIZE_ORDER: [RuleBucket; 4] = [
     RuleBucket::Reverse,
     RuleBucket::Normal,
     RuleBucket::Equiv,
     RuleBucket::Verification,
 ];
 
-enum MinimizationRoundResult {
-    Done(TableMethod),
-    NotDone(TableMethod),
+/// Return true if the order minimizes each bucket of `DEFAULT_MINIMIZE_ORDER`
+/// exactly once. Any other order could keep several rules for a class.
+pub fn is_valid_order(order: &[RuleBucket]) -> bool {
+    order.len() == DEFAULT_MINIMIZE_ORDER.len()
+        && DEFAULT_MINIMIZE_ORDER
+            .iter()
+            .all(|bucket| order.contains(bucket))
 }
 
-/// Perform one round of the minimization.
+/// Cost of a rule used to choose between the rules of a bucket.
+pub type RuleCost = Box<dyn Fn(&ForestRuleKey) -> u64>;
+
+/// Configuration of the minimization performed by the extractor.
 ///
-/// Insert into the table method until it pumps for the root class add the last
-/// rule to the maybe useful set.
-fn minimzation_bucket_round(
-    tb: TableMethod,
-    bucket: &RuleBucket,
-    root_class: u32,
-    maybe_useful: &mut HashSet<ForestRuleKey>,
-) -> MinimizationRoundResult {
-    let mut new_tb = TableMethod::new();
-    let mut rules_in_bucket = vec![];
-    for rk in tb.into_pumping_subuniverse() {
-        if rk.get_bucket() == bucket && !maybe_useful.contains(&rk) {
-            rules_in_bucket.push(rk);
-        } else {
-            new_tb.add_rule_key(rk);
-        }
-    }
-    if new_tb.is_pumping(root_class) {
-        return MinimizationRoundResult::Done(new_tb);
-    }
-    while !new_tb.is_pumping(root_class) {
-        let last_key = new_tb.add_rule_key(
-            rules_in_bucket
-                .pop()
-                .expect("Not pumping after adding all rules"),
+/// The buckets are minimized one after the other in the given order. Inside a
+/// bucket the cheapest rules are tried first, so that expensive rules are only
+/// kept when no cheaper set of rules works.
+pub struct ExtractorConfig {
+    order: Vec<RuleBucket>,
+    cost: Option<RuleCost>,
+}
+
+impl ExtractorConfig {
+    pub fn new() -> Self {
+        Self {
+            order: DEFAULT_MINIMIZE_ORDER.to_vec(),
+            cost: None,
+        }
+    }
+
+    /// Set the order in which the buckets are minimized.
+    ///
+    /// # Panic
+    ///
+    /// This function will panic if the order is not valid for `is_valid_order`.
+    pub fn with_order(self, order: Vec<RuleBucket>) -> Self {
+        assert!(
+            is_valid_order(&order),
+            "The order must contain each bucket exactly once"
         );
-        maybe_useful.insert(last_key.clone());
+        Self { order, ..self }
     }
-    MinimizationRoundResult::NotDone(new_tb)
+
+    /// Set the cost of each rule.
+    pub fn with_cost(self, cost: impl Fn(&ForestRuleKey) -> u64 + 'static) -> Self {
+        Self {
+            cost: Some(Box::new(cost)),
+            ..self
+        }
+    }
+
+    fn cost(&self, rule_key: &ForestRuleKey) -> u64 {
+        self.cost.as_ref().map_or(0, |cost| cost(rule_key))
+    }
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Property of the root class that must be preserved by the minimization.
+#[derive(Clone, Copy)]
+enum Goal {
+    /// The root class is pumping.
+    Pumping,
+    /// The root class can be counted for at least the given number of sizes.
+    Terms(u32),
 }
 
-/// Minimize the rules for a given bucket
-fn minimize_bucket(mut tb: TableMethod, bucket: &RuleBucket, root_class: u32) -> TableMethod {
-    let mut done = false;
-    let mut maybe_useful = HashSet::new();
-    while !done {
-        (tb, done) = match minimzation_bucket_round(tb, bucket, root_class, &mut maybe_useful) {
-            MinimizationRoundResult::Done(tb) => (tb, true),
-            MinimizationRoundResult::NotDone(tb) => (tb, false),
+impl Goal {
+    fn is_reached(&self, tb: &TableMethod, root_class: u32) -> bool {
+        match self {
+            Goal::Pumping => tb.is_pumping(root_class),
+            Goal::Terms(terms) => match tb.get_value(root_class) {
+                IntOrInf::Infinity => true,
+                IntOrInf::Int(value) => value >= terms,
+            },
+        }
+    }
+
+    /// Return the indices of the rules of the table method that can not help
+    /// reaching the goal.
+    fn useless_rules(&self, tb: &TableMethod) -> Vec<usize> {
+        match self {
+            Goal::Pumping => tb
+                .enumerate_rules()
+                .filter(|(_, rk)| {
+                    !tb.is_pumping(*rk.get_parent())
+                        || !rk.iter_children().all(|&c| tb.is_pumping(c))
+                })
+                .map(|(rule_idx, _)| rule_idx)
+                .collect(),
+            Goal::Terms(_) => vec![],
+        }
+    }
+}
+
+/// Remove the rules of the bucket that are not needed to reach the goal, except
+/// the forced rules.
+///
+/// The most expensive rules are tried first. Since removing rules can only make
+/// the values of the function smaller, a rule that is needed once is needed
+/// until the end of the minimization.
+fn minimize_bucket(
+    tb: &mut TableMethod,
+    bucket: &RuleBucket,
+    root_class: u32,
+    goal: Goal,
+    config: &ExtractorConfig,
+    forced: &[usize],
+) {
+    let mut rules_in_bucket: Vec<_> = tb
+        .enumerate_rules()
+        .filter(|(rule_idx, rk)| {
+            rk.get_bucket() == bucket && forced.binary_search(rule_idx).is_err()
+        })
+        .map(|(rule_idx, rk)| (rule_idx, config.cost(rk)))
+        .collect();
+    rules_in_bucket.sort_by_key(|&(_, cost)| Reverse(cost));
+    for (rule_idx, _) in rules_in_bucket {
+        tb.remove_rule(rule_idx);
+        if !goal.is_reached(tb, root_class) {
+            tb.restore_rule(rule_idx);
         }
     }
-    tb
 }
 
-/// Perform the complete minimization of the forest
-fn minimize(tb: TableMethod, root_class: u32) -> TableMethod {
-    let mut tb = tb;
-    for bucket in MINIMIZE_ORDER.iter() {
-        tb = minimize_bucket(tb, bucket, root_class);
+/// Perform the complete minimization of the forest in place, keeping the forced
+/// rules whose indices are given in increasing order.
+///
+/// Each removal only recomputes the classes that use the parent of the rule. In a
+/// recursive universe this is still the whole strongly connected component of the
+/// parent, so the minimization of a universe made of one large component stays
+/// quadratic in the number of rules.
+fn minimize(
+    mut tb: TableMethod,
+    root_class: u32,
+    goal: Goal,
+    config: &ExtractorConfig,
+    forced: &[usize],
+) -> TableMethod {
+    assert!(
+        goal.is_reached(&tb, root_class),
+        "The goal is not reached by the universe"
+    );
+    for rule_idx in goal.useless_rules(&tb) {
+        tb.remove_rule(rule_idx);
+    }
+    for bucket in config.order.iter() {
+        minimize_bucket(&mut tb, bucket, root_class, goal, config, forced);
     }
     tb
 }
 
-pub fn extract_specification(root_class: u32, tb: TableMethod) -> Vec<ForestRuleKey> {
-    let minimized = minimize(tb, root_class);
+pub fn extract_specification(
+    root_class: u32,
+    tb: TableMethod,
+    config: &ExtractorConfig,
+) -> Vec<ForestRuleKey> {
+    let minimized = minimize(tb, root_class, Goal::Pumping, config, &[]);
     let rules: Vec<_> = minimized.into_rules().collect();
     let parents: HashSet<_> = rules.iter().map(|rk| rk.get_parent()).collect();
     assert_eq!(parents.len(), rules.len());
@@ -83,9 +186,178 @@ pub fn extract_specification(root_class: u32, tb: TableMethod) -> Vec<ForestRule
     rules
 }
 
+/// Extract a set of rules, with at most one rule per class, that counts the root
+/// class for the first `terms` sizes.
+///
+/// Classes whose terms are not needed for those sizes may not have a rule.
+pub fn extract_partial_specification(
+    root_class: u32,
+    terms: u32,
+    tb: TableMethod,
+    config: &ExtractorConfig,
+) -> Vec<ForestRuleKey> {
+    let minimized = minimize(tb, root_class, Goal::Terms(terms), config, &[]);
+    let rules: Vec<_> = minimized.into_rules().collect();
+    let parents: HashSet<_> = rules.iter().map(|rk| rk.get_parent()).collect();
+    assert_eq!(parents.len(), rules.len());
+    rules
+}
+
+/// Iterator over distinct minimal specifications of a universe.
+///
+/// As in the `maybe_useful` rules of the Python extractor, some rules of the
+/// universe are forced into the specification and the other rules are minimized
+/// around them, a forced rule replacing the other rules of its class. The first
+/// specification forces no rule. Then for each new
+/// specification and each rule of the universe that is an alternative for one of
+/// its classes, that rule is forced on top of the rules forced to find the
+/// specification. The sets of forced rules are explored in breadth-first order.
+///
+/// Each explored set costs a copy of the table method of the universe and one
+/// minimization, that is one removal of each rule that is not forced. The number
+/// of explored sets is capped since the sets can grow with every specification.
+/// A set that gives no specification, because a forced rule can not replace the
+/// other rules of its class or is not used, or a specification found before, is
+/// not explored further.
+pub struct SpecificationIterator {
+    root_class: u32,
+    universe: TableMethod,
+    rules: Vec<ForestRuleKey>,
+    config: ExtractorConfig,
+    remaining: usize,
+    remaining_explorations: usize,
+    to_explore: VecDeque<Vec<usize>>,
+    explored: HashSet<Vec<usize>>,
+    found: HashSet<Vec<usize>>,
+}
+
+impl SpecificationIterator {
+    /// Create an iterator yielding at most `max_count` specifications and
+    /// minimizing the universe at most `max_explored` times.
+    pub fn new(
+        root_class: u32,
+        rules: Vec<ForestRuleKey>,
+        config: ExtractorConfig,
+        max_count: usize,
+        max_explored: usize,
+    ) -> Self {
+        let mut universe = TableMethod::new();
+        for rk in rules.iter() {
+            universe.add_rule_key(rk.clone());
+        }
+        Self {
+            root_class,
+            universe,
+            rules,
+            config,
+            remaining: max_count,
+            remaining_explorations: max_explored,
+            to_explore: VecDeque::from([vec![]]),
+            explored: HashSet::new(),
+            found: HashSet::new(),
+        }
+    }
+
+    /// Return the indices of the rules of the specification minimized around the
+    /// forced rules, in increasing order, or `None` if it is not a valid
+    /// specification.
+    fn extract(&self, forced: &[usize]) -> Option<Vec<usize>> {
+        // A forced rule replaces the other rules of its class.
+        let forced_parents: HashSet<_> =
+            forced.iter().map(|&i| self.rules[i].get_parent()).collect();
+        let replaced: Vec<_> = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(i, rk)| {
+                forced_parents.contains(rk.get_parent()) && forced.binary_search(i).is_err()
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let mut tb = self.universe.clone();
+        for rule_idx in replaced {
+            tb.remove_rule(rule_idx);
+        }
+        if !tb.is_pumping(self.root_class) {
+            return None;
+        }
+        let tb = minimize(tb, self.root_class, Goal::Pumping, &self.config, forced);
+        let spec: Vec<_> = tb.enumerate_rules().map(|(rule_idx, _)| rule_idx).collect();
+        let mut rule_of_parent = HashMap::new();
+        for &rule_idx in spec.iter() {
+            if rule_of_parent
+                .insert(*self.rules[rule_idx].get_parent(), rule_idx)
+                .is_some()
+            {
+                return None;
+            }
+        }
+        // Every rule must be reached from the root class.
+        let mut reached = HashSet::from([self.root_class]);
+        let mut to_visit = vec![self.root_class];
+        while let Some(class) = to_visit.pop() {
+            for &child in self.rules[*rule_of_parent.get(&class)?].iter_children() {
+                if reached.insert(child) {
+                    to_visit.push(child);
+                }
+            }
+        }
+        (reached.len() == spec.len()).then_some(spec)
+    }
+
+    /// Queue the sets of forced rules that replace the rule of a class of the
+    /// specification.
+    fn queue_alternatives(&mut self, forced: &[usize], spec: &[usize]) {
+        let parents: HashSet<_> = spec.iter().map(|&i| self.rules[i].get_parent()).collect();
+        let forced_parents: HashSet<_> =
+            forced.iter().map(|&i| self.rules[i].get_parent()).collect();
+        for (rule_idx, rk) in self.rules.iter().enumerate() {
+            let parent = rk.get_parent();
+            // Forcing a second rule for a class can never give a specification.
+            if !parents.contains(parent)
+                || forced_parents.contains(parent)
+                || spec.binary_search(&rule_idx).is_ok()
+            {
+                continue;
+            }
+            let mut new_forced = forced.to_vec();
+            let pos = new_forced.binary_search(&rule_idx).unwrap_err();
+            new_forced.insert(pos, rule_idx);
+            if self.explored.insert(new_forced.clone()) {
+                self.to_explore.push_back(new_forced);
+            }
+        }
+    }
+}
+
+impl Iterator for SpecificationIterator {
+    type Item = Vec<ForestRuleKey>;
+
+    fn next(&mut self) -> Option<Vec<ForestRuleKey>> {
+        if !self.universe.is_pumping(self.root_class) {
+            return None;
+        }
+        while self.remaining > 0 && self.remaining_explorations > 0 {
+            let forced = self.to_explore.pop_front()?;
+            self.remaining_explorations -= 1;
+            let spec = match self.extract(&forced) {
+                Some(spec) => spec,
+                None => continue,
+            };
+            if self.found.insert(spec.clone()) {
+                self.queue_alternatives(&forced, &spec);
+                self.remaining -= 1;
+                return Some(spec.iter().map(|&i| self.rules[i].clone()).collect());
+            }
+        }
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn extract_132_test() {
@@ -96,13 +368,370 @@ mod tests {
             ForestRuleKey::new(3, vec![4], vec![0], RuleBucket::Equiv),
             ForestRuleKey::new(4, vec![5, 0, 0], vec![0, 1, 1], RuleBucket::Normal),
             ForestRuleKey::new(5, vec![], vec![], RuleBucket::Verification),
-            ForestRuleKey::new(2, vec![6], vec![2], RuleBucket::Undefined),
+            ForestRuleKey::undefined(2, vec![6], vec![2]),
         ];
         let mut tb = TableMethod::new();
         for rule in rules.into_iter() {
             tb.add_rule_key(rule);
         }
-        let spec = extract_specification(0, tb);
+        let spec = extract_specification(0, tb, &ExtractorConfig::new());
         assert_eq!(spec.len(), 6);
     }
+
+    /// The rule for 2 is re-added before the rule that makes the root pump and
+    /// must not be kept in the specification.
+    #[test]
+    fn extract_drop_useless_rule_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(2, vec![3], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(3, vec![], vec![], RuleBucket::Verification),
+        ];
+        let mut tb = TableMethod::new();
+        for rule in rules.into_iter() {
+            tb.add_rule_key(rule);
+        }
+        let spec = extract_specification(0, tb, &ExtractorConfig::new());
+        assert_eq!(spec.len(), 2);
+    }
+
+    #[test]
+    fn extract_partial_specification_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1, 2], vec![1, 0], RuleBucket::Normal),
+            ForestRuleKey::new(2, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(1, vec![3], vec![1], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![4], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(4, vec![5], vec![1], RuleBucket::Normal),
+        ];
+        let mut tb = TableMethod::new();
+        for rule in rules.into_iter() {
+            tb.add_rule_key(rule);
+        }
+        assert_eq!(tb.get_value(0), &IntOrInf::Int(2));
+        let spec = extract_partial_specification(0, 2, tb, &ExtractorConfig::new());
+        let parents: HashSet<_> = spec.iter().map(|rk| *rk.get_parent()).collect();
+        assert!(parents.contains(&0));
+        assert!(parents.contains(&1));
+        assert!(parents.contains(&2));
+        let mut new_tb = TableMethod::new();
+        for rule in spec.into_iter() {
+            new_tb.add_rule_key(rule);
+        }
+        assert_eq!(new_tb.get_value(0), &IntOrInf::Int(2));
+    }
+
+    fn cost_universe() -> TableMethod {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+        ];
+        let mut tb = TableMethod::new();
+        for rule in rules.into_iter() {
+            tb.add_rule_key(rule);
+        }
+        tb
+    }
+
+    #[test]
+    fn extract_with_default_order_test() {
+        let spec = extract_specification(0, cost_universe(), &ExtractorConfig::new());
+        assert_eq!(
+            spec,
+            vec![ForestRuleKey::new(
+                0,
+                vec![],
+                vec![],
+                RuleBucket::Verification
+            )]
+        );
+    }
+
+    /// Minimizing the verifications first with an expensive verification for the
+    /// root keeps the structural rule instead.
+    #[test]
+    fn extract_with_order_and_cost_test() {
+        let config = ExtractorConfig::new()
+            .with_order(vec![
+                RuleBucket::Verification,
+                RuleBucket::Normal,
+                RuleBucket::Equiv,
+                RuleBucket::Reverse,
+            ])
+            .with_cost(|rk| if *rk.get_parent() == 0 { 10 } else { 1 });
+        let spec = extract_specification(0, cost_universe(), &config);
+        assert_eq!(spec.len(), 2);
+        assert!(spec.contains(&ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal)));
+        assert!(spec.contains(&ForestRuleKey::new(
+            1,
+            vec![],
+            vec![],
+            RuleBucket::Verification
+        )));
+    }
+
+    #[test]
+    fn valid_order_test() {
+        assert!(is_valid_order(&DEFAULT_MINIMIZE_ORDER));
+        assert!(!is_valid_order(&[]));
+        assert!(!is_valid_order(&DEFAULT_MINIMIZE_ORDER[1..]));
+        let mut order = DEFAULT_MINIMIZE_ORDER.to_vec();
+        order[0] = RuleBucket::Normal;
+        assert!(!is_valid_order(&order));
+        order[0] = RuleBucket::Undefined;
+        assert!(!is_valid_order(&order));
+    }
+
+    #[test]
+    #[should_panic(expected = "The order must contain each bucket exactly once")]
+    fn invalid_order_test() {
+        ExtractorConfig::new().with_order(vec![RuleBucket::Normal]);
+    }
+
+    /// The cheapest of two possible routes is kept.
+    #[test]
+    fn extract_cheapest_rule_test() {
+        let rules = [
+            ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(0, vec![2], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![], vec![], RuleBucket::Verification),
+        ];
+        for (expensive, kept) in [(1, 2), (2, 1)] {
+            let mut tb = TableMethod::new();
+            for rule in rules.iter() {
+                tb.add_rule_key(rule.clone());
+            }
+            let config = ExtractorConfig::new().with_cost(move |rk| {
+                if rk.iter_children().any(|&c| c == expensive) {
+                    10
+                } else {
+                    1
+                }
+            });
+            let spec = extract_specification(0, tb, &config);
+            assert_eq!(spec.len(), 2);
+            assert!(spec.contains(&ForestRuleKey::new(
+                0,
+                vec![kept],
+                vec![0],
+                RuleBucket::Normal
+            )));
+        }
+    }
+
+    #[test]
+    fn iterate_specifications_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(0, vec![2], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![3], vec![1], RuleBucket::Normal),
+            ForestRuleKey::new(3, vec![], vec![], RuleBucket::Verification),
+        ];
+        let specs: Vec<_> =
+            SpecificationIterator::new(0, rules.clone(), ExtractorConfig::new(), 10, 100).collect();
+        assert_eq!(specs.len(), 3);
+        let sets: HashSet<Vec<_>> = specs
+            .iter()
+            .map(|spec| {
+                let mut parents: Vec<_> = spec.iter().map(|rk| *rk.get_parent()).collect();
+                parents.sort();
+                parents
+            })
+            .collect();
+        assert!(sets.contains(&vec![0, 1]));
+        assert!(sets.contains(&vec![0, 2]));
+        assert!(sets.contains(&vec![0, 2, 3]));
+        let capped: Vec<_> =
+            SpecificationIterator::new(0, rules.clone(), ExtractorConfig::new(), 2, 100).collect();
+        assert_eq!(capped.len(), 2);
+        let explored_once: Vec<_> =
+            SpecificationIterator::new(0, rules, ExtractorConfig::new(), 10, 1).collect();
+        assert_eq!(explored_once.len(), 1);
+    }
+
+    /// Forcing the rule of 2 that is not needed is not a valid specification, and
+    /// the distinct specifications run out before the cap on explorations.
+    #[test]
+    fn iterate_specifications_forced_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(0, vec![2], vec![0], RuleBucket::Normal),
+            ForestRuleKey::new(0, vec![], vec![], RuleBucket::Verification),
+        ];
+        let mut specs = SpecificationIterator::new(0, rules, ExtractorConfig::new(), 10, 100);
+        assert!(specs.extract(&[2]).is_none());
+        assert_eq!(specs.extract(&[0]), Some(vec![0, 1]));
+        assert_eq!(specs.by_ref().count(), 3);
+        assert!(specs.remaining_explorations > 90);
+    }
+
+    #[test]
+    fn iterate_specifications_not_pumping_test() {
+        let rules = vec![ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Normal)];
+        let mut specs = SpecificationIterator::new(0, rules, ExtractorConfig::new(), 10, 100);
+        assert!(specs.next().is_none());
+    }
+
+    /// Complete binary tree of classes where each leaf is verified directly and
+    /// through an alias. All the rules of the internal classes are needed so the
+    /// minimization must check every one of them.
+    fn binary_tree_universe(depth: u32) -> TableMethod {
+        let num_internal = (1 << (depth - 1)) - 1;
+        let num_classes = (1 << depth) - 1;
+        let mut tb = TableMethod::new();
+        for class in 0..num_internal {
+            tb.add_rule_key(ForestRuleKey::new(
+                class,
+                vec![2 * class + 1, 2 * class + 2],
+                vec![0, 0],
+                RuleBucket::Normal,
+            ));
+        }
+        for leaf in num_internal..num_classes {
+            let alias = leaf + num_classes;
+            tb.add_rule_key(ForestRuleKey::new(
+                leaf,
+                vec![alias],
+                vec![0],
+                RuleBucket::Equiv,
+            ));
+            tb.add_rule_key(ForestRuleKey::new(
+                leaf,
+                vec![],
+                vec![],
+                RuleBucket::Verification,
+            ));
+            tb.add_rule_key(ForestRuleKey::new(
+                alias,
+                vec![],
+                vec![],
+                RuleBucket::Verification,
+            ));
+        }
+        tb
+    }
+
+    /// Extraction on a universe of more than 10^5 rules. Rebuilding the table
+    /// method for every needed rule would take hours on such a universe.
+    #[test]
+    #[ignore = "slow in debug builds, run with --release -- --ignored"]
+    fn extract_large_universe_test() {
+        let depth = 16;
+        let tb = binary_tree_universe(depth);
+        assert_eq!(tb.rules().count(), (1 << (depth + 1)) - 1);
+        let spec = extract_specification(0, tb, &ExtractorConfig::new());
+        assert_eq!(spec.len(), (1 << depth) - 1);
+        assert!(spec.iter().all(|rk| *rk.get_bucket() != RuleBucket::Equiv));
+    }
+
+    /// Cycle of classes where each class is also reached through an alias. Every
+    /// class uses the next one, so the whole cycle is a single strongly connected
+    /// component.
+    fn recursive_universe(num_classes: u32) -> Vec<ForestRuleKey> {
+        let mut rules = vec![];
+        for class in 0..num_classes {
+            let next = (class + 1) % num_classes;
+            let atom = class + num_classes;
+            let alias = class + 2 * num_classes;
+            rules.extend([
+                ForestRuleKey::new(class, vec![atom, next], vec![0, 1], RuleBucket::Normal),
+                ForestRuleKey::new(class, vec![alias], vec![0], RuleBucket::Equiv),
+                ForestRuleKey::new(alias, vec![atom, next], vec![0, 1], RuleBucket::Normal),
+                ForestRuleKey::new(atom, vec![], vec![], RuleBucket::Verification),
+            ]);
+        }
+        rules
+    }
+
+    /// The minimization before the table method could remove rules, which builds a
+    /// new table method for each rule it tries to remove.
+    fn minimize_by_rebuilding(
+        rules: &[ForestRuleKey],
+        root_class: u32,
+        config: &ExtractorConfig,
+    ) -> Vec<ForestRuleKey> {
+        let mut kept = rules.to_vec();
+        for bucket in config.order.iter() {
+            let in_bucket: Vec<_> = kept
+                .iter()
+                .filter(|rk| rk.get_bucket() == bucket)
+                .cloned()
+                .collect();
+            for rule in in_bucket {
+                let mut tb = TableMethod::new();
+                for rk in kept.iter().filter(|rk| **rk != rule) {
+                    tb.add_rule_key(rk.clone());
+                }
+                if tb.is_pumping(root_class) {
+                    kept.retain(|rk| *rk != rule);
+                }
+            }
+        }
+        kept
+    }
+
+    /// Time the minimization of the universe and the rebuilding minimization, and
+    /// check that they keep the same rules.
+    fn time_minimizations(rules: &[ForestRuleKey]) -> (Duration, Duration) {
+        let config = ExtractorConfig::new();
+        let start = Instant::now();
+        let mut tb = TableMethod::new();
+        for rule in rules.iter() {
+            tb.add_rule_key(rule.clone());
+        }
+        let mut spec = extract_specification(0, tb, &config);
+        let incremental = start.elapsed();
+        let start = Instant::now();
+        let mut expected = minimize_by_rebuilding(rules, 0, &config);
+        let rebuilding = start.elapsed();
+        spec.sort_by_key(|rk| *rk.get_parent());
+        expected.sort_by_key(|rk| *rk.get_parent());
+        assert_eq!(spec, expected);
+        (incremental, rebuilding)
+    }
+
+    /// The minimization keeps the same rules as the rebuilding minimization, with
+    /// and without recursion.
+    #[test]
+    fn minimize_like_rebuilding_test() {
+        time_minimizations(&binary_tree_universe(5).rules().cloned().collect::<Vec<_>>());
+        time_minimizations(&recursive_universe(10));
+    }
+
+    /// Benchmark against the rebuilding minimization on a universe without
+    /// recursion, where each removal only recomputes the ancestors of a leaf.
+    #[test]
+    #[ignore = "wall-clock benchmark, run with --release -- --ignored"]
+    fn minimize_tree_benchmark_test() {
+        let rules: Vec<_> = binary_tree_universe(8).rules().cloned().collect();
+        let (incremental, rebuilding) = time_minimizations(&rules);
+        assert!(
+            incremental * 10 < rebuilding,
+            "incremental: {:?}, rebuilding: {:?}",
+            incremental,
+            rebuilding
+        );
+    }
+
+    /// Benchmark against the rebuilding minimization on a recursive universe. Each
+    /// removal recomputes the whole cycle, so the speed-up is only a constant.
+    #[test]
+    #[ignore = "wall-clock benchmark, run with --release -- --ignored"]
+    fn minimize_recursive_benchmark_test() {
+        let rules = recursive_universe(60);
+        let (incremental, rebuilding) = time_minimizations(&rules);
+        assert!(
+            incremental < rebuilding,
+            "incremental: {:?}, rebuilding: {:?}",
+            incremental,
+            rebuilding
+        );
+    }
 }
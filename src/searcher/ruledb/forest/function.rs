@@ -1,6 +1,8 @@
-#[derive(Clone, Debug, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IntOrInf {
-    Int(u8),
+    Int(u32),
     Infinity,
 }
 
@@ -20,6 +22,7 @@ impl IntOrInf {
 /// A representation of a function from N to N U {∞}
 ///
 /// The default value of th function is 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Function {
     values: Vec<IntOrInf>,
     preimage_count: Vec<u32>,
@@ -46,7 +49,7 @@ impl Function {
         match old_value {
             Some(IntOrInf::Int(value)) => {
                 self.preimage_count[*value as usize] -= 1;
-                *value += 1;
+                *value = value.checked_add(1).expect("Function value overflow");
                 if self.preimage_count.len() <= *value as usize {
                     self.preimage_count.resize(*value as usize + 1, 0);
                 }
@@ -76,13 +79,31 @@ impl Function {
             }
             Some(IntOrInf::Infinity) => (),
             None => {
+                if self.preimage_count.is_empty() {
+                    self.preimage_count.push(0);
+                }
                 self.preimage_count[0] += input - self.values.len() as u32;
-                self.preimage_count.resize(2, 0);
-                self.preimage_count[1] += 1;
+                self.infinity_count += 1;
                 self.values.resize(input as usize, IntOrInf::Int(0));
-                self.values.push(IntOrInf::Int(1));
+                self.values.push(IntOrInf::Infinity);
+            }
+        }
+    }
+
+    /// Set the value back to 0 for the given input
+    pub fn reset(&mut self, input: u32) {
+        match self.values.get_mut(input as usize) {
+            Some(IntOrInf::Int(value)) => {
+                self.preimage_count[*value as usize] -= 1;
+                *value = 0;
+            }
+            Some(value) => {
+                self.infinity_count -= 1;
+                *value = IntOrInf::Int(0);
             }
+            None => return,
         }
+        self.preimage_count[0] += 1;
     }
 
     /// Number of value for which a value is registered
@@ -90,19 +111,39 @@ impl Function {
         self.values.len() as u32
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
     /// Return the preimage of the given input
     ///
     /// # Panic
     ///
     /// This function will panic on a value of 0 as the preimage is not well
     /// defined.
-    pub fn preimage(&self, value: IntOrInf) -> FunctionPreImageIterator {
+    pub fn preimage(&self, value: IntOrInf) -> FunctionPreImageIterator<'_> {
         match value {
             IntOrInf::Int(0) => panic!("The preimage of 0 is infinite"),
             _ => FunctionPreImageIterator::new(self, value),
         }
     }
 
+    /// Return true if the preimage counts are the counts of the values.
+    pub fn has_consistent_counts(&self) -> bool {
+        let mut preimage_count = vec![0; self.preimage_count.len()];
+        let mut infinity_count = 0;
+        for value in self.values.iter() {
+            match value {
+                IntOrInf::Int(value) => match preimage_count.get_mut(*value as usize) {
+                    Some(count) => *count += 1,
+                    None => return false,
+                },
+                IntOrInf::Infinity => infinity_count += 1,
+            }
+        }
+        preimage_count == self.preimage_count && infinity_count == self.infinity_count
+    }
+
     /// Return the smallest k such that the preimage of the interval
     /// [k, k+length-1] is empty.
     ///
@@ -123,13 +164,11 @@ impl Function {
         }
         last_non_zero + 1
     }
+}
 
-    ///Increase the value vector to include the last input
-    fn increase_value_vector(&mut self, last_input: u32) {
-        let num_new_entry = last_input - self.values.len() as u32 + 1;
-        self.values
-            .resize(last_input as usize + 1, IntOrInf::Int(0));
-        self.preimage_count[0] += num_new_entry;
+impl Default for Function {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -140,7 +179,7 @@ pub struct FunctionPreImageIterator<'a> {
 }
 
 impl<'a> FunctionPreImageIterator<'a> {
-    fn new(function: &Function, value: IntOrInf) -> FunctionPreImageIterator {
+    fn new(function: &Function, value: IntOrInf) -> FunctionPreImageIterator<'_> {
         FunctionPreImageIterator {
             function,
             value,
@@ -157,7 +196,7 @@ impl<'a> Iterator for FunctionPreImageIterator<'a> {
             let value_for_pos = self.function.get_value(self.pos);
             self.pos += 1;
             if *value_for_pos == self.value {
-                return Some(self.pos as u32 - 1);
+                return Some(self.pos - 1);
             }
         }
         None
@@ -170,7 +209,8 @@ mod tests {
 
     #[test]
     fn create_function() {
-        let mut f = Function::new();
+        let f = Function::new();
+        assert!(f.is_empty());
     }
 
     #[test]
@@ -228,6 +268,20 @@ mod tests {
         assert_eq!(*f.get_value(4), IntOrInf::Int(1));
     }
 
+    /// An input without a registered value becomes infinite, not 1, and is
+    /// counted in the preimage of infinity.
+    #[test]
+    fn infinity_of_new_input() {
+        let mut f = Function::new();
+        f.set_infinite(2);
+        assert_eq!(*f.get_value(0), IntOrInf::Int(0));
+        assert_eq!(*f.get_value(1), IntOrInf::Int(0));
+        assert_eq!(*f.get_value(2), IntOrInf::Infinity);
+        assert_eq!(f.preimage(IntOrInf::Infinity).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(f.preimage_count, vec![2]);
+        assert_eq!(f.preimage_gap(1), 1);
+    }
+
     #[test]
     fn preimage_inf() {
         let mut f = Function::new();
@@ -334,4 +388,46 @@ mod tests {
         assert_eq!(f.preimage_gap(2), 4);
         assert_eq!(f.preimage_gap(3), 4);
     }
+
+    #[test]
+    fn large_value() {
+        let mut f = Function::new();
+        for _ in 0..300 {
+            f.increase_value(2);
+        }
+        assert_eq!(*f.get_value(2), IntOrInf::Int(300));
+        assert_eq!(f.preimage(IntOrInf::Int(300)).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(f.preimage_gap(1), 1);
+    }
+
+    #[test]
+    fn json_infinity() {
+        let mut f = Function::new();
+        f.increase_value(0);
+        f.set_infinite(2);
+        let json = serde_json::to_string(&f).unwrap();
+        let restored: Function = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, f);
+        assert_eq!(restored.get_value(0), &IntOrInf::Int(1));
+        assert_eq!(restored.get_value(1), &IntOrInf::Int(0));
+        assert_eq!(restored.get_value(2), &IntOrInf::Infinity);
+    }
+
+    #[test]
+    fn reset() {
+        let mut f = Function::new();
+        f.increase_value(1);
+        f.increase_value(1);
+        f.set_infinite(3);
+        f.reset(1);
+        f.reset(3);
+        f.reset(10);
+        assert_eq!(f.get_value(1), &IntOrInf::Int(0));
+        assert_eq!(f.get_value(3), &IntOrInf::Int(0));
+        assert_eq!(f.preimage(IntOrInf::Infinity).count(), 0);
+        assert_eq!(f.preimage(IntOrInf::Int(2)).count(), 0);
+        assert_eq!(f.preimage_gap(1), 1);
+        f.increase_value(3);
+        assert_eq!(f.preimage(IntOrInf::Int(1)).collect::<Vec<_>>(), vec![3]);
+    }
 }
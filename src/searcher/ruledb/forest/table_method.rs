@@ -1,17 +1,56 @@
 use super::{Function, IntOrInf};
+use crate::pack::{Rule, Strategy};
 use core::slice::Iter;
+use serde::{Deserialize, Serialize};
 use std;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Category of a rule, deciding in which order the extractor tries to remove it.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum RuleBucket {
+    /// Rule that is never removed by the minimization. This is never derived from
+    /// a rule and must be chosen explicitly with `ForestRuleKey::undefined`.
     Undefined,
+    /// Rule without children.
     Verification,
+    /// Equivalence rule with a single child.
     Equiv,
+    /// Any other rule.
     Normal,
+    /// Rule computing a child of a reversible rule.
     Reverse,
 }
 
+impl RuleBucket {
+    /// Return the bucket of the rule.
+    pub fn from_rule<S: Strategy>(rule: &Rule<S>) -> RuleBucket {
+        let num_children = rule.get_children().len();
+        if rule.is_reverse() {
+            RuleBucket::Reverse
+        } else if num_children == 0 {
+            RuleBucket::Verification
+        } else if num_children == 1 && rule.is_equivalence() {
+            RuleBucket::Equiv
+        } else {
+            RuleBucket::Normal
+        }
+    }
+
+    /// Return true if a rule with the given shifts, one per child, can be in the
+    /// bucket. An equivalence and a reverse rule preserve the size of the objects,
+    /// so their shifts are 0.
+    fn accepts(&self, shifts: &[i32]) -> bool {
+        match self {
+            RuleBucket::Undefined => true,
+            RuleBucket::Verification => shifts.is_empty(),
+            RuleBucket::Equiv => shifts == [0],
+            RuleBucket::Normal => !shifts.is_empty(),
+            RuleBucket::Reverse => !shifts.is_empty() && shifts.iter().all(|&s| s == 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct RuleClassConnector {
     rule_using_class: HashMap<u32, Vec<(usize, usize)>>,
     rule_pumping_class: HashMap<u32, Vec<usize>>,
@@ -49,28 +88,118 @@ impl RuleClassConnector {
             .unwrap_or([][..].iter())
     }
 
-    pub fn remove_class_information(&mut self, class: u32) {
-        todo!("This implementation is not correct");
+    /// Remove all the information about the given rules.
+    pub fn remove_rules(&mut self, rule_indices: &BTreeSet<usize>, rules: &[ForestRuleKey]) {
+        let mut parents = HashSet::new();
+        let mut children = HashSet::new();
+        for &rule_idx in rule_indices.iter() {
+            parents.insert(rules[rule_idx].parent);
+            children.extend(rules[rule_idx].children.iter().copied());
+        }
+        for parent in parents {
+            if let Some(rules_pumping) = self.rule_pumping_class.get_mut(&parent) {
+                rules_pumping.retain(|r_idx| !rule_indices.contains(r_idx));
+                if rules_pumping.is_empty() {
+                    self.rule_pumping_class.remove(&parent);
+                }
+            }
+        }
+        for child in children {
+            if let Some(rules_using) = self.rule_using_class.get_mut(&child) {
+                rules_using.retain(|(r_idx, _)| !rule_indices.contains(r_idx));
+                if rules_using.is_empty() {
+                    self.rule_using_class.remove(&child);
+                }
+            }
+        }
+    }
+
+    /// Remove all the information about a class whose value will never change
+    /// again.
+    ///
+    /// The rules pumping the class are also removed from the rules using each of
+    /// their children since they will never need to be corrected.
+    pub fn remove_class_information(&mut self, class: u32, rules: &[ForestRuleKey]) {
+        for rule_idx in self.rule_pumping_class.remove(&class).unwrap_or_default() {
+            for child in rules[rule_idx].children.iter() {
+                if let Some(rules_using_child) = self.rule_using_class.get_mut(child) {
+                    rules_using_child.retain(|&(r_idx, _)| r_idx != rule_idx);
+                    if rules_using_child.is_empty() {
+                        self.rule_using_class.remove(child);
+                    }
+                }
+            }
+        }
         self.rule_using_class.remove(&class);
-        self.rule_pumping_class.remove(&class);
     }
 }
 
-#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedForestRuleKey")]
 pub struct ForestRuleKey {
     parent: u32,
     children: Vec<u32>,
-    shifts: Vec<i8>,
+    shifts: Vec<i32>,
     bucket: RuleBucket,
 }
 
+/// A forest rule key read from JSON, before its shifts are checked against its
+/// bucket.
+#[derive(Deserialize)]
+struct UncheckedForestRuleKey {
+    parent: u32,
+    children: Vec<u32>,
+    shifts: Vec<i32>,
+    bucket: RuleBucket,
+}
+
+impl TryFrom<UncheckedForestRuleKey> for ForestRuleKey {
+    type Error = String;
+
+    fn try_from(key: UncheckedForestRuleKey) -> Result<Self, Self::Error> {
+        ForestRuleKey::check(&key.children, &key.shifts, &key.bucket)?;
+        Ok(ForestRuleKey {
+            parent: key.parent,
+            children: key.children,
+            shifts: key.shifts,
+            bucket: key.bucket,
+        })
+    }
+}
+
 impl ForestRuleKey {
-    pub fn new(
+    /// Create a key in the given bucket, for the tests that build keys without a
+    /// rule. Otherwise the bucket of a rule is always derived by `from_rule`, and
+    /// a rule is kept out of the minimization with `undefined`.
+    #[cfg(test)]
+    pub(crate) fn new(
         parent: u32,
         children: Vec<u32>,
-        shifts: Vec<i8>,
+        shifts: Vec<i32>,
         bucket: RuleBucket,
     ) -> ForestRuleKey {
+        assert_ne!(
+            bucket,
+            RuleBucket::Undefined,
+            "A key in the Undefined bucket must be created with ForestRuleKey::undefined"
+        );
+        ForestRuleKey::unchecked_bucket(parent, children, shifts, bucket)
+    }
+
+    /// Create the key of a rule that is never removed by the minimization.
+    pub fn undefined(parent: u32, children: Vec<u32>, shifts: Vec<i32>) -> ForestRuleKey {
+        ForestRuleKey::unchecked_bucket(parent, children, shifts, RuleBucket::Undefined)
+    }
+
+    fn unchecked_bucket(
+        parent: u32,
+        children: Vec<u32>,
+        shifts: Vec<i32>,
+        bucket: RuleBucket,
+    ) -> ForestRuleKey {
+        if let Err(message) = ForestRuleKey::check(&children, &shifts, &bucket) {
+            panic!("{}", message);
+        }
         ForestRuleKey {
             parent,
             children,
@@ -79,6 +208,41 @@ impl ForestRuleKey {
         }
     }
 
+    /// Check that there is one shift per child and that the bucket accepts the
+    /// shifts.
+    fn check(children: &[u32], shifts: &[i32], bucket: &RuleBucket) -> Result<(), String> {
+        if children.len() != shifts.len() {
+            return Err(String::from("There must be one shift per child"));
+        }
+        if !bucket.accepts(shifts) {
+            return Err(format!(
+                "A rule with the shifts {:?} can not be in the {:?} bucket",
+                shifts, bucket
+            ));
+        }
+        Ok(())
+    }
+
+    /// Create the key of the rule between the given labels. The shifts and the
+    /// bucket are derived from the rule.
+    pub fn from_rule<S: Strategy>(
+        parent: u32,
+        children: Vec<u32>,
+        rule: &Rule<S>,
+    ) -> ForestRuleKey {
+        assert_eq!(
+            children.len(),
+            rule.get_children().len(),
+            "There must be one label per child of the rule"
+        );
+        ForestRuleKey::unchecked_bucket(
+            parent,
+            children,
+            rule.get_shifts(),
+            RuleBucket::from_rule(rule),
+        )
+    }
+
     pub fn key(&self) -> (&u32, &Vec<u32>) {
         (&self.parent, &self.children)
     }
@@ -91,21 +255,29 @@ impl ForestRuleKey {
         &self.parent
     }
 
-    pub fn iter_children(&self) -> Iter<u32> {
+    pub fn iter_children(&self) -> Iter<'_, u32> {
         self.children.iter()
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TableMethod {
     rules: Vec<ForestRuleKey>,
-    shifts: Vec<Vec<Option<i8>>>,
+    shifts: Vec<Vec<Option<i32>>>,
     function: Function,
     gap_size: u32,
     // Both for rule using and rule pumping class
     rule_class_connector: RuleClassConnector,
     processing_queue: VecDeque<usize>,
     current_gap: (u32, u32),
-    rule_holding_extra_terms: HashSet<usize>,
+    // Ordered so that the rules are queued in the same order by a table method
+    // restored from JSON.
+    rule_holding_extra_terms: BTreeSet<usize>,
+    // Removed rules keep their index so that the other indices stay valid.
+    removed: HashSet<usize>,
+    // Indices of the rules that are not removed by parent and by child.
+    rules_by_parent: HashMap<u32, Vec<usize>>,
+    rules_by_child: HashMap<u32, Vec<usize>>,
 }
 
 impl TableMethod {
@@ -118,25 +290,184 @@ impl TableMethod {
             rule_class_connector: RuleClassConnector::new(),
             processing_queue: VecDeque::new(),
             current_gap: (1, 1),
-            rule_holding_extra_terms: HashSet::new(),
+            rule_holding_extra_terms: BTreeSet::new(),
+            removed: HashSet::new(),
+            rules_by_parent: HashMap::new(),
+            rules_by_child: HashMap::new(),
+        }
+    }
+
+    /// Serialize the complete state of the table method to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Table method is always serializable")
+    }
+
+    /// Restore a table method from the JSON produced by `to_json`.
+    ///
+    /// The shifts of each rule must be accepted by its bucket, every rule index of
+    /// the state must refer to one of the rules, and the values of the function
+    /// must be the ones the rules give. The rest of the bookkeeping, such as the
+    /// queues and the rule class connector, is trusted to be the one `to_json`
+    /// wrote.
+    pub fn from_json(json: &str) -> serde_json::Result<TableMethod> {
+        let tb: TableMethod = serde_json::from_str(json)?;
+        tb.check_indices().map_err(serde::de::Error::custom)?;
+        tb.check_values().map_err(serde::de::Error::custom)?;
+        Ok(tb)
+    }
+
+    /// Check that the state has shifts for each rule and only refers to existing
+    /// rules.
+    fn check_indices(&self) -> Result<(), String> {
+        let num_rules = self.rules.len();
+        if self.shifts.len() != num_rules {
+            return Err(String::from("There must be shifts for each rule"));
+        }
+        let connector = &self.rule_class_connector;
+        let indices = self
+            .processing_queue
+            .iter()
+            .chain(self.rule_holding_extra_terms.iter())
+            .chain(self.removed.iter())
+            .chain(self.rules_by_parent.values().flatten())
+            .chain(self.rules_by_child.values().flatten())
+            .chain(connector.rule_pumping_class.values().flatten())
+            .chain(
+                connector
+                    .rule_using_class
+                    .values()
+                    .flatten()
+                    .map(|(r, _)| r),
+            );
+        if let Some(rule_idx) = indices.into_iter().find(|&&r| r >= num_rules) {
+            return Err(format!("There is no rule with index {}", rule_idx));
+        }
+        for (rule_key, shifts) in self.rules.iter().zip(self.shifts.iter()) {
+            // The shifts of a rule are only computed once it is indexed.
+            if !shifts.is_empty() && shifts.len() != rule_key.children.len() {
+                return Err(String::from("There must be one shift per child"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the function gives each class the value of a new table method
+    /// with the rules that are not removed.
+    fn check_values(&self) -> Result<(), String> {
+        if !self.function.has_consistent_counts() {
+            return Err(String::from("The preimage counts do not match the values"));
+        }
+        let mut tb = TableMethod::new();
+        for rule_key in self.rules() {
+            tb.add_rule_key(rule_key.clone());
+        }
+        let num_classes = std::cmp::max(self.function.len(), tb.function.len());
+        match (0..num_classes).find(|&c| self.get_value(c) != tb.get_value(c)) {
+            Some(class) => Err(format!(
+                "The value of the class {} is not the one its rules give",
+                class
+            )),
+            None => Ok(()),
         }
     }
 
     /// Add the rule to the database
     pub fn add_rule_key(&mut self, rule_key: ForestRuleKey) -> &ForestRuleKey {
         self.rules.push(rule_key);
-        let rule_key = self.rules.last().unwrap();
-        self.shifts.push(self.compute_shift(&rule_key));
-        let max_gap = rule_key.shifts.iter().map(|s| s.abs()).max().unwrap_or(0) as u32;
+        self.shifts.push(vec![]);
+        let rule_idx = self.rules.len() - 1;
+        self.insert_rule(rule_idx);
+        self.process_queue();
+        &self.rules[rule_idx]
+    }
+
+    /// Remove the rule with the given index from the universe.
+    ///
+    /// Only the classes that use the parent of the rule, directly or not, are
+    /// recomputed. The indices of the other rules are not changed.
+    pub fn remove_rule(&mut self, rule_idx: usize) {
+        assert!(self.removed.insert(rule_idx), "The rule is already removed");
+        let rule_key = &self.rules[rule_idx];
+        let parent = rule_key.parent;
+        self.rules_by_parent
+            .get_mut(&parent)
+            .unwrap()
+            .retain(|&r| r != rule_idx);
+        for child in rule_key.children.iter() {
+            self.rules_by_child
+                .get_mut(child)
+                .unwrap()
+                .retain(|&r| r != rule_idx);
+        }
+        let mut rules_to_update = BTreeSet::from([rule_idx]);
+        if *self.function.get_value(parent) == IntOrInf::Int(0) {
+            // The rule never gave any term so nothing depends on it.
+            self.rule_class_connector
+                .remove_rules(&rules_to_update, &self.rules);
+            return;
+        }
+        let affected_classes = self.classes_using(parent);
+        for &class in affected_classes.iter() {
+            self.function.reset(class);
+            rules_to_update.extend(
+                self.rules_by_parent
+                    .get(&class)
+                    .into_iter()
+                    .flatten()
+                    .copied(),
+            );
+        }
+        self.rule_class_connector
+            .remove_rules(&rules_to_update, &self.rules);
+        let k = self.function.preimage_gap(self.gap_size);
+        self.current_gap = (k, k + self.gap_size);
+        rules_to_update.remove(&rule_idx);
+        for rule_idx in rules_to_update {
+            self.shifts[rule_idx] = self.compute_shift(&self.rules[rule_idx]);
+            self.register_rule(rule_idx);
+        }
+        self.process_queue();
+    }
+
+    /// Add back a rule previously removed with `remove_rule`.
+    pub fn restore_rule(&mut self, rule_idx: usize) {
+        assert!(self.removed.remove(&rule_idx), "The rule is not removed");
+        self.insert_rule(rule_idx);
+        self.process_queue();
+    }
+
+    /// Index the rule, correct the gap size and queue the rule.
+    fn insert_rule(&mut self, rule_idx: usize) {
+        let rule_key = &self.rules[rule_idx];
+        self.rules_by_parent
+            .entry(rule_key.parent)
+            .or_default()
+            .push(rule_idx);
+        for child in rule_key.children.iter() {
+            self.rules_by_child
+                .entry(*child)
+                .or_default()
+                .push(rule_idx);
+        }
+        self.shifts[rule_idx] = self.compute_shift(rule_key);
+        let max_gap = rule_key
+            .shifts
+            .iter()
+            .map(|s| s.unsigned_abs())
+            .max()
+            .unwrap_or(0);
         if max_gap > self.gap_size {
             self.gap_size = max_gap;
             self.correct_gap();
         }
-        // Because the correct gap need a mutable reference to self we need to
-        // invalidate the immutable reference hold by rule_key.
-        let rule_key = self.rules.last().unwrap();
+        self.register_rule(rule_idx);
+    }
+
+    /// Register the rule in the rule class connector and queue it, unless its
+    /// parent is already pumping.
+    fn register_rule(&mut self, rule_idx: usize) {
+        let rule_key = &self.rules[rule_idx];
         if self.function.get_value(rule_key.parent).is_finite() {
-            let rule_idx = self.rules.len() - 1;
             self.rule_class_connector
                 .add_rule_pumping_class(rule_key.parent, rule_idx);
             for (child_idx, child) in rule_key.children.iter().enumerate() {
@@ -147,9 +478,21 @@ impl TableMethod {
             }
             self.processing_queue.push_back(rule_idx);
         }
-        self.process_queue();
-        let rule_key = self.rules.last().unwrap();
-        rule_key
+    }
+
+    /// Return the class and all the classes whose value may depend on it.
+    fn classes_using(&self, class: u32) -> HashSet<u32> {
+        let mut classes = HashSet::from([class]);
+        let mut to_visit = vec![class];
+        while let Some(c) = to_visit.pop() {
+            for &rule_idx in self.rules_by_child.get(&c).into_iter().flatten() {
+                let parent = self.rules[rule_idx].parent;
+                if classes.insert(parent) {
+                    to_visit.push(parent);
+                }
+            }
+        }
+        classes
     }
 
     /// Determine if the comb_class is pumping in the current universe.
@@ -157,6 +500,12 @@ impl TableMethod {
         self.function.get_value(class).is_infinite()
     }
 
+    /// Return the number of terms of the class that can be computed in the current
+    /// universe.
+    pub fn get_value(&self, class: u32) -> &IntOrInf {
+        self.function.get_value(class)
+    }
+
     pub fn stable_subset(&self) -> impl Iterator<Item = u32> + '_ {
         self.function.preimage(IntOrInf::Infinity)
     }
@@ -164,7 +513,7 @@ impl TableMethod {
     /// Iterator over all the forest rule keys that contain only pumping
     /// combinatorial classes.
     pub fn pumping_subuniverse(&self) -> impl Iterator<Item = &ForestRuleKey> {
-        self.rules.iter().filter(move |forest_key| {
+        self.rules().filter(move |forest_key| {
             self.is_pumping(forest_key.parent)
                 && forest_key.children.iter().all(|c| self.is_pumping(*c))
         })
@@ -174,7 +523,7 @@ impl TableMethod {
     /// combinatorial classes.
     pub fn into_pumping_subuniverse(self) -> impl Iterator<Item = ForestRuleKey> {
         let stable_subset: HashSet<_> = self.stable_subset().collect();
-        self.rules.into_iter().filter(move |forest_key| {
+        self.into_rules().filter(move |forest_key| {
             stable_subset.contains(&forest_key.parent)
                 && forest_key
                     .children
@@ -183,14 +532,32 @@ impl TableMethod {
         })
     }
 
+    /// Iterator over all the forest rule keys.
+    pub fn rules(&self) -> impl Iterator<Item = &ForestRuleKey> {
+        self.enumerate_rules().map(|(_, rule_key)| rule_key)
+    }
+
+    /// Iterator over all the forest rule keys with their index.
+    pub fn enumerate_rules(&self) -> impl Iterator<Item = (usize, &ForestRuleKey)> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(rule_idx, _)| !self.removed.contains(rule_idx))
+    }
+
     /// Consumes self and returns all the forest rule keys it contains.
     pub fn into_rules(self) -> impl Iterator<Item = ForestRuleKey> {
-        self.rules.into_iter()
+        let removed = self.removed;
+        self.rules
+            .into_iter()
+            .enumerate()
+            .filter(move |(rule_idx, _)| !removed.contains(rule_idx))
+            .map(|(_, rule_key)| rule_key)
     }
 
     /// Compute the initial value for the shifts a rule based on the current state of
     /// the function.
-    fn compute_shift(&self, rule_key: &ForestRuleKey) -> Vec<Option<i8>> {
+    fn compute_shift(&self, rule_key: &ForestRuleKey) -> Vec<Option<i32>> {
         let parent_curent_value = self.function.get_value(rule_key.parent);
         match parent_curent_value {
             IntOrInf::Infinity => vec![None; rule_key.children.len()],
@@ -204,7 +571,9 @@ impl TableMethod {
                     .map(|(fvalue, sfz)| match fvalue {
                         IntOrInf::Infinity => None,
                         IntOrInf::Int(fvalue) => {
-                            Some(*fvalue as i8 + sfz - *parent_curent_value as i8)
+                            let shift = i64::from(*fvalue) + i64::from(*sfz)
+                                - i64::from(*parent_curent_value);
+                            Some(i32::try_from(shift).expect("Shift overflow"))
                         }
                     })
                     .collect()
@@ -238,8 +607,7 @@ impl TableMethod {
                     self.increase_value(parent, rule_idx);
                 }
             }
-            if let Some(&rule_idx) = self.rule_holding_extra_terms.iter().next() {
-                self.rule_holding_extra_terms.remove(&rule_idx);
+            if let Some(rule_idx) = self.rule_holding_extra_terms.pop_first() {
                 let parent = self.rules[rule_idx].parent;
                 self.set_infinite(parent);
             }
@@ -247,8 +615,8 @@ impl TableMethod {
     }
 
     /// Return true if the shifts indicate that a new term can be computed
-    fn can_give_terms(shifts: &Vec<Option<i8>>) -> bool {
-        shifts.iter().all(|&s| s.map_or(true, |x| x > 0))
+    fn can_give_terms(shifts: &[Option<i32>]) -> bool {
+        shifts.iter().all(|&s| s.is_none_or(|x| x > 0))
     }
 
     /// Increase the value of the comb_class and put on the processing stack any rule
@@ -261,7 +629,7 @@ impl TableMethod {
             IntOrInf::Infinity => return,
             IntOrInf::Int(v) => *v,
         };
-        if current_value as u32 > self.current_gap.1 {
+        if current_value > self.current_gap.1 {
             self.rule_holding_extra_terms.insert(rule_idx);
             return;
         }
@@ -273,9 +641,9 @@ impl TableMethod {
         }
         // Correction of shifts for rule pumping class
         for &r_idx in self.rule_class_connector.get_rules_pumping_class(class) {
-            let mut shifts = self.shifts.get_mut(r_idx).unwrap();
+            let shifts = self.shifts.get_mut(r_idx).unwrap();
             for v in shifts.iter_mut() {
-                *v = v.map(|v| v - 1);
+                *v = v.map(|v| v.checked_sub(1).expect("Shift overflow"));
             }
             if TableMethod::can_give_terms(shifts) {
                 self.processing_queue.push_back(r_idx)
@@ -283,9 +651,9 @@ impl TableMethod {
         }
         // Correction of the shifts for rule using the class
         for &(r_idx, class_idx) in self.rule_class_connector.get_rules_using_class(class) {
-            let mut shifts = self.shifts.get_mut(r_idx).unwrap();
-            let mut current_shift = shifts.get_mut(class_idx).unwrap();
-            *current_shift = current_shift.map(|v| v + 1);
+            let shifts = self.shifts.get_mut(r_idx).unwrap();
+            let current_shift = shifts.get_mut(class_idx).unwrap();
+            *current_shift = current_shift.map(|v| v.checked_add(1).expect("Shift overflow"));
             if TableMethod::can_give_terms(shifts) {
                 self.processing_queue.push_back(r_idx);
             }
@@ -302,37 +670,50 @@ impl TableMethod {
             IntOrInf::Infinity => return,
             IntOrInf::Int(v) => v,
         };
-        assert!(*current_value as u32 > self.current_gap.1);
+        assert!(*current_value > self.current_gap.1);
         assert!(self.processing_queue.is_empty());
         self.function.set_infinite(class);
-        // This class will never be increased again so we remove any occurrence
-        // of the rule of any rule for that class from _rules_using_class and
-        //_rules_pumping_class
-        // TODO: implement that later since its only for saving memory
-        // for rule_idx in self._rules_pumping_class[comb_class]:
-        //     for child in self._rules[rule_idx].children:
-        //         self._rules_using_class[child] = [
-        //             (ri, ci)
-        //             for ri, ci in self._rules_using_class[child]
-        //             if ri != rule_idx
-        //         ]
-        // self._rules_pumping_class[comb_class].clear()
         // Correction of the shifts for rules using comb_class to pump
         for &(rule_idx, class_idx) in self.rule_class_connector.get_rules_using_class(class) {
-            let mut shifts = self.shifts.get_mut(rule_idx).unwrap();
+            let shifts = self.shifts.get_mut(rule_idx).unwrap();
             shifts[class_idx] = None;
             if TableMethod::can_give_terms(shifts) {
                 self.processing_queue.push_back(rule_idx)
             }
         }
-        // TODO: same as above
-        // self._rules_using_class[comb_class].clear()
+        // This class will never be increased again so the rules for that class
+        // and the rules using it never need to be corrected again.
+        self.rule_class_connector
+            .remove_class_information(class, &self.rules);
+    }
+}
+
+impl Default for TableMethod {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Number of entries stored in the rule class connector.
+    fn connector_size(tb: &TableMethod) -> usize {
+        let connector = &tb.rule_class_connector;
+        connector
+            .rule_using_class
+            .values()
+            .map(|v| v.len())
+            .sum::<usize>()
+            + connector
+                .rule_pumping_class
+                .values()
+                .map(|v| v.len())
+                .sum::<usize>()
+    }
 
     /// The universe consist of the rule of the usual 132 tree plus a dummy rule that is
     /// useless.
@@ -345,7 +726,7 @@ mod tests {
             ForestRuleKey::new(3, vec![4], vec![0], RuleBucket::Equiv),
             ForestRuleKey::new(4, vec![5, 0, 0], vec![0, 1, 1], RuleBucket::Normal),
             ForestRuleKey::new(5, vec![], vec![], RuleBucket::Verification),
-            ForestRuleKey::new(2, vec![6], vec![2], RuleBucket::Undefined),
+            ForestRuleKey::undefined(2, vec![6], vec![2]),
         ];
         let mut tb = TableMethod::new();
         for rule in rules.into_iter() {
@@ -376,7 +757,7 @@ mod tests {
 
     /// The universe consist of the rule of the usual 132 tree plus a dummy rule that is
     /// useless.
-
+    ///
     /// We add rule progressively and make sure the function is always up to date.
     #[test]
     fn universe132_pumping_progressive_test() {
@@ -429,12 +810,7 @@ mod tests {
         assert_eq!(tb.function.get_value(4), &IntOrInf::Int(0));
         assert_eq!(tb.function.get_value(5), &IntOrInf::Infinity);
         // Dumb rule
-        tb.add_rule_key(ForestRuleKey::new(
-            2,
-            vec![6],
-            vec![-2],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(2, vec![6], vec![-2]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(0));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(2), &IntOrInf::Int(0));
@@ -443,12 +819,7 @@ mod tests {
         assert_eq!(tb.function.get_value(5), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(6), &IntOrInf::Int(0));
         // Dumb rule. This will pump 2 and 0 a little bit
-        tb.add_rule_key(ForestRuleKey::new(
-            2,
-            vec![7],
-            vec![2],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(2, vec![7], vec![2]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(2), &IntOrInf::Int(2));
@@ -499,53 +870,28 @@ mod tests {
     fn segmented_test() {
         let mut tb = TableMethod::new();
 
-        tb.add_rule_key(ForestRuleKey::new(
-            0,
-            vec![1, 2],
-            vec![0, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            1,
-            vec![4, 14],
-            vec![0, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(2, vec![], vec![], RuleBucket::Undefined));
+        tb.add_rule_key(ForestRuleKey::undefined(0, vec![1, 2], vec![0, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(1, vec![4, 14], vec![0, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(2, vec![], vec![]));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
 
-        tb.add_rule_key(ForestRuleKey::new(
-            3,
-            vec![16, 5],
-            vec![1, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(4, vec![], vec![], RuleBucket::Undefined));
-        tb.add_rule_key(ForestRuleKey::new(5, vec![], vec![], RuleBucket::Undefined));
+        tb.add_rule_key(ForestRuleKey::undefined(3, vec![16, 5], vec![1, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(4, vec![], vec![]));
+        tb.add_rule_key(ForestRuleKey::undefined(5, vec![], vec![]));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(3), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(4), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(5), &IntOrInf::Infinity);
 
         // Induced a gap size change
-        tb.add_rule_key(ForestRuleKey::new(
-            6,
-            vec![7, 5, 17],
-            vec![2, 1, 1],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(6, vec![7, 5, 17], vec![2, 1, 1]));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(3), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(4), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(5), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(6), &IntOrInf::Int(1));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            16,
-            vec![6],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(16, vec![6], vec![0]));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(3), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(4), &IntOrInf::Infinity);
@@ -553,13 +899,8 @@ mod tests {
         assert_eq!(tb.function.get_value(6), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(16), &IntOrInf::Int(1));
 
-        tb.add_rule_key(ForestRuleKey::new(7, vec![], vec![], RuleBucket::Undefined));
-        tb.add_rule_key(ForestRuleKey::new(
-            8,
-            vec![9, 5],
-            vec![1, 0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(7, vec![], vec![]));
+        tb.add_rule_key(ForestRuleKey::undefined(8, vec![9, 5], vec![1, 0]));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
         assert_eq!(tb.function.get_value(3), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(4), &IntOrInf::Infinity);
@@ -569,36 +910,11 @@ mod tests {
         assert_eq!(tb.function.get_value(8), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(16), &IntOrInf::Int(1));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            12,
-            vec![20, 5],
-            vec![-1, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            20,
-            vec![13],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            13,
-            vec![15, 2, 5],
-            vec![-1, 1, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            15,
-            vec![1],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            14,
-            vec![3],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(12, vec![20, 5], vec![-1, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(20, vec![13], vec![0]));
+        tb.add_rule_key(ForestRuleKey::undefined(13, vec![15, 2, 5], vec![-1, 1, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(15, vec![1], vec![0]));
+        tb.add_rule_key(ForestRuleKey::undefined(14, vec![3], vec![0]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
@@ -614,18 +930,8 @@ mod tests {
         assert_eq!(tb.function.get_value(16), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(20), &IntOrInf::Int(1));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            18,
-            vec![8],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            11,
-            vec![12, 18],
-            vec![0, 0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(18, vec![8], vec![0]));
+        tb.add_rule_key(ForestRuleKey::undefined(11, vec![12, 18], vec![0, 0]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Int(2));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
@@ -642,12 +948,7 @@ mod tests {
         assert_eq!(tb.function.get_value(18), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(20), &IntOrInf::Int(1));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            17,
-            vec![8],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(17, vec![8], vec![0]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(3));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Int(3));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
@@ -667,18 +968,8 @@ mod tests {
         assert_eq!(tb.function.get_value(18), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(20), &IntOrInf::Int(2));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            9,
-            vec![0, 19],
-            vec![0, 0],
-            RuleBucket::Undefined,
-        ));
-        tb.add_rule_key(ForestRuleKey::new(
-            10,
-            vec![5, 11],
-            vec![0, 1],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(9, vec![0, 19], vec![0, 0]));
+        tb.add_rule_key(ForestRuleKey::undefined(10, vec![5, 11], vec![0, 1]));
         assert_eq!(tb.function.get_value(0), &IntOrInf::Int(3));
         assert_eq!(tb.function.get_value(1), &IntOrInf::Int(3));
         assert_eq!(tb.function.get_value(2), &IntOrInf::Infinity);
@@ -699,13 +990,314 @@ mod tests {
         assert_eq!(tb.function.get_value(18), &IntOrInf::Int(1));
         assert_eq!(tb.function.get_value(20), &IntOrInf::Int(2));
 
-        tb.add_rule_key(ForestRuleKey::new(
-            19,
-            vec![10],
-            vec![0],
-            RuleBucket::Undefined,
-        ));
+        tb.add_rule_key(ForestRuleKey::undefined(19, vec![10], vec![0]));
         assert!((0..21).all(|c| tb.function.get_value(c) == &IntOrInf::Infinity));
         assert!((0..21).all(|c| tb.is_pumping(c)));
     }
+
+    #[test]
+    fn connector_cleared_when_pumping_test() {
+        let mut tb = TableMethod::new();
+        for i in 0..100 {
+            tb.add_rule_key(ForestRuleKey::new(
+                i,
+                vec![i + 1],
+                vec![0],
+                RuleBucket::Equiv,
+            ));
+        }
+        assert_eq!(connector_size(&tb), 200);
+        tb.add_rule_key(ForestRuleKey::new(
+            100,
+            vec![],
+            vec![],
+            RuleBucket::Verification,
+        ));
+        assert!((0..101).all(|c| tb.is_pumping(c)));
+        assert_eq!(connector_size(&tb), 0);
+    }
+
+    #[test]
+    fn connector_size_with_pumping_classes_test() {
+        let mut tb = TableMethod::new();
+        tb.add_rule_key(ForestRuleKey::new(
+            0,
+            vec![1, 2],
+            vec![0, 0],
+            RuleBucket::Normal,
+        ));
+        tb.add_rule_key(ForestRuleKey::new(
+            1,
+            vec![],
+            vec![],
+            RuleBucket::Verification,
+        ));
+        let size = connector_size(&tb);
+        assert_eq!(size, 2);
+        for i in 3..1003 {
+            tb.add_rule_key(ForestRuleKey::new(i, vec![1], vec![1], RuleBucket::Normal));
+            tb.add_rule_key(ForestRuleKey::new(1, vec![i], vec![0], RuleBucket::Normal));
+        }
+        assert!((3..1003).all(|c| tb.is_pumping(c)));
+        assert!(!tb.is_pumping(0));
+        assert_eq!(connector_size(&tb), size);
+    }
+
+    /// A shift that does not fit in 8 bits forces values above 255 before the class
+    /// can be set to infinity.
+    #[test]
+    fn large_shift_test() {
+        let mut tb = TableMethod::new();
+        tb.add_rule_key(ForestRuleKey::new(
+            0,
+            vec![1, 0],
+            vec![0, 300],
+            RuleBucket::Normal,
+        ));
+        assert_eq!(tb.function.get_value(0), &IntOrInf::Int(0));
+        tb.add_rule_key(ForestRuleKey::new(
+            1,
+            vec![],
+            vec![],
+            RuleBucket::Verification,
+        ));
+        assert!(tb.is_pumping(0));
+        assert!(tb.is_pumping(1));
+    }
+
+    #[test]
+    fn json_round_trip_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1, 2], vec![0, 0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![3], vec![0], RuleBucket::Equiv),
+            ForestRuleKey::new(3, vec![4], vec![0], RuleBucket::Equiv),
+            ForestRuleKey::new(4, vec![5, 0, 0], vec![0, 1, 1], RuleBucket::Normal),
+            ForestRuleKey::undefined(2, vec![6], vec![2]),
+        ];
+        let mut tb = TableMethod::new();
+        for rule in rules.into_iter() {
+            tb.add_rule_key(rule);
+        }
+        let mut restored = TableMethod::from_json(&tb.to_json()).unwrap();
+        assert_eq!(restored, tb);
+        assert_eq!(restored.get_value(1), &IntOrInf::Infinity);
+        assert_eq!(restored.get_value(0), &IntOrInf::Int(2));
+        // The restored table method keeps working from the same state, so it ends in
+        // the same state as the original.
+        let last_rule = ForestRuleKey::new(5, vec![], vec![], RuleBucket::Verification);
+        tb.add_rule_key(last_rule.clone());
+        restored.add_rule_key(last_rule);
+        assert_eq!(restored, tb);
+        assert!(restored.is_pumping(0));
+    }
+
+    /// A restored table method replays additions and removals exactly like the
+    /// original.
+    #[test]
+    fn json_replay_random_test() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let rules = random_rules(&mut rng, 8, 12);
+            let mut tb = TableMethod::new();
+            for rule in rules[..6].iter() {
+                tb.add_rule_key(rule.clone());
+            }
+            let mut restored = TableMethod::from_json(&tb.to_json()).unwrap();
+            assert_eq!(restored, tb);
+            for rule in rules[6..].iter() {
+                tb.add_rule_key(rule.clone());
+                restored.add_rule_key(rule.clone());
+            }
+            tb.remove_rule(0);
+            restored.remove_rule(0);
+            assert_eq!(restored, tb);
+            assert_eq!(TableMethod::from_json(&tb.to_json()).unwrap(), tb);
+        }
+    }
+
+    #[test]
+    fn json_invalid_test() {
+        assert!(TableMethod::from_json("{\"rules\": []}").is_err());
+    }
+
+    #[test]
+    fn json_invalid_rule_test() {
+        let mut tb = TableMethod::new();
+        tb.add_rule_key(ForestRuleKey::new(
+            0,
+            vec![],
+            vec![],
+            RuleBucket::Verification,
+        ));
+        let json = tb.to_json();
+        assert!(TableMethod::from_json(&json).is_ok());
+        let misfiled = json.replace("\"Verification\"", "\"Normal\"");
+        assert!(TableMethod::from_json(&misfiled).is_err());
+        let missing_shift = json.replace("\"children\":[]", "\"children\":[1]");
+        assert!(TableMethod::from_json(&missing_shift).is_err());
+        let unknown_rule = json.replace("\"removed\":[]", "\"removed\":[3]");
+        assert!(TableMethod::from_json(&unknown_rule).is_err());
+        let removed_rule = json.replace("\"removed\":[]", "\"removed\":[0]");
+        assert!(TableMethod::from_json(&removed_rule).is_err());
+        let wrong_value = json.replace("\"values\":[\"Infinity\"]", "\"values\":[{\"Int\":1}]");
+        assert_ne!(wrong_value, json);
+        assert!(TableMethod::from_json(&wrong_value).is_err());
+        let mut tampered = tb.clone();
+        tampered.function.increase_value(1);
+        assert!(tampered.function.has_consistent_counts());
+        assert!(TableMethod::from_json(&tampered.to_json()).is_err());
+    }
+
+    /// Values of the function for the first classes.
+    fn values(tb: &TableMethod, num_classes: u32) -> Vec<IntOrInf> {
+        (0..num_classes).map(|c| tb.get_value(c).clone()).collect()
+    }
+
+    fn random_rules(rng: &mut StdRng, num_classes: u32, num_rules: usize) -> Vec<ForestRuleKey> {
+        (0..num_rules)
+            .map(|_| {
+                let parent = rng.gen_range(0..num_classes);
+                let num_children = rng.gen_range(0..3);
+                let children: Vec<_> = (0..num_children)
+                    .map(|_| rng.gen_range(0..num_classes))
+                    .collect();
+                let shifts = children.iter().map(|_| rng.gen_range(0..3)).collect();
+                let bucket = if children.is_empty() {
+                    RuleBucket::Verification
+                } else {
+                    RuleBucket::Normal
+                };
+                ForestRuleKey::new(parent, children, shifts, bucket)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn remove_rule_132_test() {
+        let rules = vec![
+            ForestRuleKey::new(0, vec![1, 2], vec![0, 0], RuleBucket::Normal),
+            ForestRuleKey::new(1, vec![], vec![], RuleBucket::Verification),
+            ForestRuleKey::new(2, vec![3], vec![0], RuleBucket::Equiv),
+            ForestRuleKey::new(3, vec![4], vec![0], RuleBucket::Equiv),
+            ForestRuleKey::new(4, vec![5, 0, 0], vec![0, 1, 1], RuleBucket::Normal),
+            ForestRuleKey::new(5, vec![], vec![], RuleBucket::Verification),
+        ];
+        let mut tb = TableMethod::new();
+        for rule in rules.into_iter() {
+            tb.add_rule_key(rule);
+        }
+        assert!(tb.is_pumping(0));
+        tb.remove_rule(5);
+        assert_eq!(
+            values(&tb, 6),
+            vec![
+                IntOrInf::Int(0),
+                IntOrInf::Infinity,
+                IntOrInf::Int(0),
+                IntOrInf::Int(0),
+                IntOrInf::Int(0),
+                IntOrInf::Int(0),
+            ]
+        );
+        assert_eq!(tb.rules().count(), 5);
+        tb.restore_rule(5);
+        assert!(tb.is_pumping(0));
+        assert_eq!(tb.rules().count(), 6);
+    }
+
+    /// Removing a rule gives the same values as building the table method without
+    /// that rule.
+    #[test]
+    fn remove_rule_random_test() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let rules = random_rules(&mut rng, 8, 12);
+            let mut tb = TableMethod::new();
+            for rule in rules.iter() {
+                tb.add_rule_key(rule.clone());
+            }
+            let all_values = values(&tb, 8);
+            let mut removed = HashSet::new();
+            for _ in 0..4 {
+                let rule_idx = rng.gen_range(0..rules.len());
+                if !removed.insert(rule_idx) {
+                    continue;
+                }
+                tb.remove_rule(rule_idx);
+                let mut expected = TableMethod::new();
+                for (i, rule) in rules.iter().enumerate() {
+                    if !removed.contains(&i) {
+                        expected.add_rule_key(rule.clone());
+                    }
+                }
+                assert_eq!(values(&tb, 8), values(&expected, 8));
+            }
+            for rule_idx in removed {
+                tb.restore_rule(rule_idx);
+            }
+            assert_eq!(values(&tb, 8), all_values);
+        }
+    }
+
+    #[test]
+    fn bucket_from_rule_test() {
+        use crate::word::{AvoidingWithPrefix, WordStrategy};
+        let patterns = vec![String::from("aa")];
+        let alphabet = vec!['a', 'b'];
+        let word = |prefix: &str| {
+            AvoidingWithPrefix::new(String::from(prefix), patterns.clone(), alphabet.clone())
+        };
+        let just = |prefix: &str| {
+            AvoidingWithPrefix::new_just_prefix(
+                String::from(prefix),
+                patterns.clone(),
+                alphabet.clone(),
+            )
+        };
+        let expansion = Rule::new(word("a"), WordStrategy::Expansion);
+        assert_eq!(RuleBucket::from_rule(&expansion), RuleBucket::Normal);
+        assert_eq!(
+            RuleBucket::from_rule(&expansion.to_reverse_rule(1)),
+            RuleBucket::Reverse
+        );
+        let atom = Rule::new(just("a"), WordStrategy::Atom);
+        assert_eq!(RuleBucket::from_rule(&atom), RuleBucket::Verification);
+        let remove_front = Rule::new(word("ab"), WordStrategy::RemoveFrontOfPrefix);
+        assert_eq!(RuleBucket::from_rule(&remove_front), RuleBucket::Normal);
+        let rule_key = ForestRuleKey::from_rule(0, vec![1, 2], &remove_front);
+        assert_eq!(rule_key.get_bucket(), &RuleBucket::Normal);
+        assert_eq!(rule_key.shifts, remove_front.get_shifts());
+    }
+
+    #[test]
+    #[should_panic(expected = "A rule with the shifts [0] can not be in the Verification bucket")]
+    fn misfiled_bucket_test() {
+        ForestRuleKey::new(0, vec![1], vec![0], RuleBucket::Verification);
+    }
+
+    /// A rule with a single child that changes the size is not an equivalence.
+    #[test]
+    #[should_panic(expected = "A rule with the shifts [1] can not be in the Equiv bucket")]
+    fn shifted_equivalence_test() {
+        ForestRuleKey::new(0, vec![1], vec![1], RuleBucket::Equiv);
+    }
+
+    #[test]
+    #[should_panic(expected = "A rule with the shifts [0, 1] can not be in the Reverse bucket")]
+    fn shifted_reverse_rule_test() {
+        ForestRuleKey::new(0, vec![1, 2], vec![0, 1], RuleBucket::Reverse);
+    }
+
+    #[test]
+    fn undefined_bucket_test() {
+        ForestRuleKey::undefined(0, vec![], vec![]);
+        ForestRuleKey::undefined(0, vec![1, 2], vec![0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be created with ForestRuleKey::undefined")]
+    fn implicit_undefined_bucket_test() {
+        ForestRuleKey::new(0, vec![], vec![], RuleBucket::Undefined);
+    }
 }
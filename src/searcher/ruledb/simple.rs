@@ -1,50 +1,337 @@
 use super::classdb;
 use super::{RuleDB, RuleLabel};
-use crate::errors::SpecificationNotFoundError;
+use crate::errors::{SearchError, SpecificationNotFoundError};
 use crate::pack::Rule;
 use crate::pack::Strategy;
 use crate::searcher::equiv_db;
 use crate::specification::CombinatorialSpecification;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{RngCore, SeedableRng};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::rc::Rc;
+
+mod productive;
+mod proof_trees;
+mod well_founded;
+use productive::ProductiveClasses;
+use proof_trees::ProofTreeIterator;
+use well_founded::ShiftedRule;
+
+/// How the rule of each class of the specification is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleSelection {
+    /// Pick a rule at random with the random number generator of the database.
+    Random,
+    /// Pick the rule with the lowest label.
+    LowestLabel,
+    /// Find the specification with the fewest rules.
+    ///
+    /// The search is a branch and bound that is exponential in the worst case. Its
+    /// result is kept until a rule or an equivalence that can change it is added.
+    Smallest,
+}
+
+/// A concrete rule with the equivalence labels of its children and their shifts.
+type RuleOption = (RuleLabel, Vec<(usize, i32)>);
+
+/// The rank of each class that has a well-founded specification.
+type Ranks = HashMap<usize, usize>;
+
+/// The rules in term of equivalence labels that can be used in a well-founded
+/// specification, by parent and sorted, with the rank of each class.
+type WellFoundedRules = Rc<(HashMap<usize, Vec<RuleLabel>>, Ranks)>;
+
+/// A value computed for a root, kept until the productive rules change.
+struct Cached<T> {
+    root: usize,
+    generation: u64,
+    value: T,
+}
+
+impl<T> Cached<T> {
+    /// Return the value if it was computed for the root and the productive rules
+    /// have not changed since.
+    fn get(cached: &Option<Self>, root: usize, generation: u64) -> Option<&T> {
+        cached
+            .as_ref()
+            .filter(|c| c.root == root && c.generation == generation)
+            .map(|c| &c.value)
+    }
+}
+
+/// Preference of a strategy when several strategies give the same rule. The
+/// strategy with the lowest preference is used in the specifications.
+pub type StrategyPreference<S> = Box<dyn Fn(&S) -> u64>;
 
 pub struct SimpleRuleDB<S: Strategy> {
-    rule_to_strategy: HashMap<RuleLabel, S>,
+    /// The strategies of each rule in the order they were found, with the shift of
+    /// each child of the rule.
+    rule_to_strategies: HashMap<RuleLabel, Vec<(S, Vec<i32>)>>,
     equiv_db: equiv_db::EquivDB,
+    productive: ProductiveClasses,
+    rule_selection: RuleSelection,
+    strategy_preference: Option<StrategyPreference<S>>,
+    rng: Box<dyn RngCore>,
+    /// The last well-founded rules, computed for an equivalence label.
+    well_founded: Option<Cached<WellFoundedRules>>,
+    /// The last smallest specification, or `None` if there was none.
+    smallest: Option<Cached<Option<Vec<RuleLabel>>>>,
 }
 
 impl<S: Strategy> SimpleRuleDB<S> {
     pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    /// Create a database that always gives the same specification for the same
+    /// rules and seed.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    /// Create a database that picks the rules of the specification with the given
+    /// random number generator.
+    pub fn with_rng(rng: impl RngCore + 'static) -> Self {
         Self {
-            rule_to_strategy: HashMap::new(),
+            rule_to_strategies: HashMap::new(),
             equiv_db: equiv_db::EquivDB::new(),
+            productive: ProductiveClasses::new(),
+            rule_selection: RuleSelection::Random,
+            strategy_preference: None,
+            rng: Box::new(rng),
+            well_founded: None,
+            smallest: None,
         }
     }
 
-    /// Find a specification in term of the equivalence label
+    pub fn set_rule_selection(&mut self, rule_selection: RuleSelection) {
+        self.rule_selection = rule_selection;
+    }
+
+    /// Set the preference of the strategies that give the same rule. By default,
+    /// the first strategy found is used.
+    pub fn set_strategy_preference(&mut self, preference: impl Fn(&S) -> u64 + 'static) {
+        self.strategy_preference = Some(Box::new(preference));
+    }
+
+    /// Return the strategies that give the rule, in the order they were found.
+    pub fn get_strategies<'a>(&'a self, rule: &RuleLabel) -> impl Iterator<Item = &'a S> + 'a {
+        self.rule_to_strategies
+            .get(rule)
+            .into_iter()
+            .flatten()
+            .map(|(strategy, _)| strategy)
+    }
+
+    /// Return the strategy used for the rule in the specifications.
+    ///
+    /// A rule with an equivalence strategy only connects equivalent classes, so it
+    /// needs an equivalence strategy. Otherwise, the well-founded specifications
+    /// are found with the shifts of the first strategy of the rule, so only the
+    /// strategies that shift every child at least as much can replace it.
+    fn preferred_strategy(&self, rule: &RuleLabel) -> &S {
+        let strategies = &self.rule_to_strategies[rule];
+        let is_equivalence = rule.get_children().len() == 1
+            && strategies
+                .iter()
+                .any(|(strategy, _)| strategy.is_equivalence());
+        let first_shifts = &strategies[0].1;
+        let mut candidates = strategies
+            .iter()
+            .filter(|(strategy, shifts)| {
+                if is_equivalence {
+                    strategy.is_equivalence()
+                } else {
+                    shifts.iter().zip(first_shifts).all(|(s, f)| s >= f)
+                }
+            })
+            .map(|(strategy, _)| strategy);
+        match &self.strategy_preference {
+            None => candidates.next(),
+            Some(preference) => candidates.min_by_key(|s| preference(s)),
+        }
+        .unwrap()
+    }
+
+    /// Build the specification of the root from its rules.
+    fn to_specification(
+        &self,
+        root: usize,
+        specification_rules: Vec<RuleLabel>,
+        classdb: &classdb::ClassDB<S::ClassType>,
+    ) -> io::Result<CombinatorialSpecification<S>> {
+        let actual_rules = specification_rules
+            .into_iter()
+            .map(|rule| {
+                let parent = classdb.get_class_from_label(*rule.get_parent())?.unwrap();
+                let strategy = self.preferred_strategy(&rule).clone();
+                Ok(Rule::new(parent, strategy))
+            })
+            .collect::<io::Result<Vec<Rule<S>>>>()?;
+        Ok(CombinatorialSpecification {
+            rules: actual_rules,
+            root: classdb.get_class_from_label(root)?.unwrap(),
+        })
+    }
+
+    /// Count the well-founded specifications of the class with the given label,
+    /// that is the ways to pick one productive rule for each equivalence class
+    /// reachable from it such that the children that are not shifted never lead
+    /// back to the parent. Every class has a single rule, so a cycle can not be
+    /// unfolded and the count is always finite. Return `None` if it does not fit
+    /// in a `u128`.
+    pub fn count_specifications(&mut self, label: usize) -> Option<u128> {
+        let eqv_label = self.equiv_db.find(label);
+        proof_trees::count_proof_trees(&self.rule_options(), eqv_label)
+    }
+
+    /// Iterate lazily over at most `limit` distinct well-founded specifications of
+    /// the class with the given label.
+    ///
+    /// Each specification picks one productive rule for each reachable equivalence
+    /// class. The choices are tried depth first, a choice is dropped as soon as its
+    /// rules form a cycle of children that are not shifted, and only the current
+    /// choice is kept in memory.
+    pub fn iter_specifications<'a>(
+        &'a mut self,
+        label: usize,
+        classdb: &'a classdb::ClassDB<S::ClassType>,
+        limit: usize,
+    ) -> impl Iterator<Item = io::Result<CombinatorialSpecification<S>>> + 'a {
+        let eqv_label = self.equiv_db.find(label);
+        ProofTreeIterator::new(self.rule_options(), eqv_label, limit).map(move |rules| {
+            let specification_rules = self
+                .connect_equivalences(label, rules)
+                .expect("The classes of an equivalence class are connected");
+            self.to_specification(label, specification_rules, classdb)
+        })
+    }
+
+    /// Return the productive rules that shift no child by a negative amount, by
+    /// equivalence label of their parent and sorted, with the equivalence label
+    /// and the shift of each child.
+    fn rule_options(&self) -> HashMap<usize, Vec<RuleOption>> {
+        let mut options: HashMap<usize, Vec<RuleOption>> = HashMap::new();
+        for (&eqv_parent, eqv_rules) in self.productive.productive_rules().iter() {
+            let options_for_parent = options.entry(eqv_parent).or_default();
+            for eqv_rule in eqv_rules {
+                for (rule, shifted_children) in self.productive.rules_with_label(eqv_rule) {
+                    if shifted_children.iter().all(|(_, shift)| *shift >= 0) {
+                        options_for_parent.push((rule.clone(), shifted_children.to_vec()));
+                    }
+                }
+            }
+            options_for_parent.sort();
+        }
+        options
+    }
+
+    /// Return the rules in term of equivalence labels that can be used in a
+    /// well-founded specification of the root, by parent and sorted, with the rank
+    /// of each class.
+    ///
+    /// A rule is kept if one of its rules only uses without shift classes of lower
+    /// rank, so any choice of the kept rules gives a specification that can count
+    /// the root. The ranks are only computed once the root is productive, since
+    /// every class of a well-founded specification is productive, and they are
+    /// kept until the productive rules change.
+    fn well_founded_rules(&mut self, eqv_root: usize) -> WellFoundedRules {
+        if !self.productive.is_productive(eqv_root) {
+            return WellFoundedRules::default();
+        }
+        let generation = self.productive.generation();
+        if let Some(well_founded) = Cached::get(&self.well_founded, eqv_root, generation) {
+            return well_founded.clone();
+        }
+        let productive_rules = self.productive.productive_rules();
+        let mut shifted_rules: HashMap<usize, Vec<ShiftedRule>> = HashMap::new();
+        for (parent, eqv_rules) in productive_rules.iter() {
+            let shifted_rules_for_parent = shifted_rules.entry(*parent).or_default();
+            for eqv_rule in eqv_rules {
+                shifted_rules_for_parent.extend(
+                    self.productive
+                        .rules_with_label(eqv_rule)
+                        .map(|(_, shifted_children)| shifted_children),
+                );
+            }
+        }
+        let ranks = well_founded::well_founded_ranks(&shifted_rules, eqv_root);
+        let rules = productive_rules
+            .iter()
+            .filter(|(parent, _)| ranks.contains_key(parent))
+            .map(|(&parent, eqv_rules)| {
+                let eqv_rules = eqv_rules
+                    .iter()
+                    .filter(|eqv_rule| self.find_rule_from_eqv_rule(eqv_rule, &ranks).is_some())
+                    .cloned()
+                    .collect();
+                (parent, eqv_rules)
+            })
+            .collect();
+        let well_founded = Rc::new((rules, ranks));
+        self.well_founded = Some(Cached {
+            root: eqv_root,
+            generation,
+            value: well_founded.clone(),
+        });
+        well_founded
+    }
+
+    /// Find a specification of the class with the given label.
     fn find_specification(
         &mut self,
         label: usize,
     ) -> Result<Vec<RuleLabel>, SpecificationNotFoundError> {
-        let rules = self.rule_up_to_equivalence();
-        let rules = prune(rules);
-        random_proof_tree(&rules, label)
+        let eqv_label = self.equiv_db.find(label);
+        let well_founded = self.well_founded_rules(eqv_label);
+        let (rules, ranks) = &*well_founded;
+        if !rules.contains_key(&eqv_label) {
+            return Err(SpecificationNotFoundError {});
+        }
+        let rng: Option<&mut dyn RngCore> = match self.rule_selection {
+            RuleSelection::Random => Some(&mut self.rng),
+            RuleSelection::LowestLabel => None,
+            RuleSelection::Smallest => return self.smallest_specification(label, rules, ranks),
+        };
+        let eqv_specification_rules = proof_tree(rules, eqv_label, rng)?;
+        self.eqv_specification_to_specification(label, eqv_specification_rules, ranks)
     }
 
     /// Convert a specification in term of equivalence labels in to
     /// specification in term of actual labels.
     fn eqv_specification_to_specification(
         &mut self,
+        root: usize,
         eqv_specification_rules: Vec<RuleLabel>,
-    ) -> Vec<RuleLabel> {
-        let mut children: Vec<usize> = vec![];
-        let specification_rules_by_eqv_parent: HashMap<_, _> = eqv_specification_rules
-            .into_iter()
+        ranks: &Ranks,
+    ) -> Result<Vec<RuleLabel>, SpecificationNotFoundError> {
+        let rules = eqv_specification_rules
+            .iter()
             .map(|eqv_rule| {
-                let rule = self.find_rule_from_eqv_rule(&eqv_rule).unwrap().clone();
+                self.find_rule_from_eqv_rule(eqv_rule, ranks)
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+        self.connect_equivalences(root, rules)
+    }
+
+    /// Add to the rules, one for each equivalence class, the equivalence rules that
+    /// connect the root and every child to the parent of the rule of their
+    /// equivalence class.
+    fn connect_equivalences(
+        &mut self,
+        root: usize,
+        rules: Vec<RuleLabel>,
+    ) -> Result<Vec<RuleLabel>, SpecificationNotFoundError> {
+        let mut children: Vec<usize> = vec![root];
+        let specification_rules_by_eqv_parent: HashMap<_, _> = rules
+            .into_iter()
+            .map(|rule| {
                 children.extend(rule.get_children());
-                (*eqv_rule.get_parent(), rule)
+                (self.equiv_db.find(*rule.get_parent()), rule)
             })
             .collect();
         let mut specification_rules = HashSet::new();
@@ -52,7 +339,10 @@ impl<S: Strategy> SimpleRuleDB<S> {
             let child_eqv_label = self.equiv_db.find(child);
             let parent_to_connect =
                 *specification_rules_by_eqv_parent[&child_eqv_label].get_parent();
-            let path = self.equiv_db.find_path(child, parent_to_connect);
+            let path = self
+                .equiv_db
+                .find_path(child, parent_to_connect)
+                .map_err(|_| SpecificationNotFoundError {})?;
             for pair in path.windows(2) {
                 specification_rules.insert(RuleLabel {
                     parent: pair[0],
@@ -61,21 +351,141 @@ impl<S: Strategy> SimpleRuleDB<S> {
             }
         }
         specification_rules.extend(specification_rules_by_eqv_parent.into_values());
-        specification_rules.into_iter().collect()
+        let mut specification_rules: Vec<_> = specification_rules.into_iter().collect();
+        specification_rules.sort();
+        Ok(specification_rules)
+    }
+
+    /// Return the well-founded specification of the root with the fewest rules, the
+    /// equivalence rules included.
+    ///
+    /// Every class of a specification has exactly one rule, so it is also the
+    /// specification with the fewest classes. The search is a branch and bound on
+    /// the rules of the ranked classes, so it is exponential in the worst case, and
+    /// its result is kept until the productive rules or the equivalences change.
+    fn smallest_specification(
+        &mut self,
+        root: usize,
+        eqv_rules_by_parent: &HashMap<usize, Vec<RuleLabel>>,
+        ranks: &Ranks,
+    ) -> Result<Vec<RuleLabel>, SpecificationNotFoundError> {
+        let eqv_root = self.equiv_db.find(root);
+        if !eqv_rules_by_parent.contains_key(&eqv_root) {
+            return Err(SpecificationNotFoundError {});
+        }
+        let generation = self.productive.generation();
+        if let Some(smallest) = Cached::get(&self.smallest, root, generation) {
+            return smallest.clone().ok_or(SpecificationNotFoundError {});
+        }
+        // A specification can combine rules that are not kept in the well-founded
+        // universe, as long as the rules that do not shift never form a cycle.
+        let mut options = self.rule_options();
+        options.retain(|eqv_parent, _| ranks.contains_key(eqv_parent));
+        for options_for_parent in options.values_mut() {
+            options_for_parent.retain(|(_, shifted_children)| {
+                shifted_children.iter().all(|(c, _)| ranks.contains_key(c))
+            });
+            options_for_parent.sort_by(|(r1, c1), (r2, c2)| (c1.len(), r1).cmp(&(c2.len(), r2)));
+        }
+        let mut best = None;
+        self.branch(root, &options, &mut HashMap::new(), &mut best);
+        self.smallest = Some(Cached {
+            root,
+            generation,
+            value: best.clone(),
+        });
+        best.ok_or(SpecificationNotFoundError {})
     }
 
-    fn find_rule_from_eqv_rule(&mut self, eqv_rule: &RuleLabel) -> Option<&RuleLabel> {
-        for rule in self.rule_to_strategy.keys() {
-            if *eqv_rule == self.equiv_db.rule_up_to_equivalence(rule) {
-                return Some(rule);
+    /// Try every option for the lowest equivalence class that still needs a rule.
+    ///
+    /// The chosen options are indexed by equivalence class.
+    fn branch(
+        &mut self,
+        root: usize,
+        options: &HashMap<usize, Vec<RuleOption>>,
+        chosen: &mut HashMap<usize, usize>,
+        best: &mut Option<Vec<RuleLabel>>,
+    ) {
+        let eqv_root = self.equiv_db.find(root);
+        let mut seen = HashSet::from([eqv_root]);
+        let mut stack = vec![eqv_root];
+        let mut without_rule = vec![];
+        while let Some(eqv_label) = stack.pop() {
+            match chosen.get(&eqv_label) {
+                Some(&idx) => {
+                    let (_, shifted_children) = &options[&eqv_label][idx];
+                    stack.extend(
+                        shifted_children
+                            .iter()
+                            .map(|&(c, _)| c)
+                            .filter(|&c| seen.insert(c)),
+                    );
+                }
+                None => without_rule.push(eqv_label),
+            }
+        }
+        let best_len = best.as_ref().map_or(usize::MAX, |b| b.len());
+        if chosen.len() + without_rule.len() >= best_len {
+            return;
+        }
+        match without_rule.into_iter().min() {
+            Some(eqv_label) => {
+                for idx in 0..options[&eqv_label].len() {
+                    chosen.insert(eqv_label, idx);
+                    self.branch(root, options, chosen, best);
+                }
+                chosen.remove(&eqv_label);
+            }
+            None => {
+                let shifted_rules: HashMap<usize, Vec<ShiftedRule>> = chosen
+                    .iter()
+                    .map(|(&eqv_label, &idx)| {
+                        let (_, shifted_children) = &options[&eqv_label][idx];
+                        (eqv_label, vec![shifted_children.as_slice()])
+                    })
+                    .collect();
+                if !well_founded::well_founded_ranks(&shifted_rules, eqv_root)
+                    .contains_key(&eqv_root)
+                {
+                    return;
+                }
+                let rules = chosen
+                    .iter()
+                    .map(|(eqv_label, &idx)| options[eqv_label][idx].0.clone())
+                    .collect();
+                if let Ok(specification) = self.connect_equivalences(root, rules) {
+                    if specification.len() < best_len {
+                        *best = Some(specification);
+                    }
+                }
             }
         }
-        None
     }
 
+    /// Return the lowest rule with the given label in term of equivalence labels
+    /// that can be used in a well-founded specification.
+    fn find_rule_from_eqv_rule(&self, eqv_rule: &RuleLabel, ranks: &Ranks) -> Option<&RuleLabel> {
+        self.productive
+            .rules_with_label(eqv_rule)
+            .filter(|(_, shifted_children)| {
+                well_founded::is_well_founded_rule(ranks, *eqv_rule.get_parent(), shifted_children)
+            })
+            .map(|(rule, _)| rule)
+            .min()
+    }
+
+    /// Return the rules in term of equivalence labels.
+    ///
+    /// The equivalence rules are left out since they are kept in the equivalence
+    /// database and would only give a rule from a class to itself.
+    #[cfg(test)]
     fn rule_up_to_equivalence(&mut self) -> HashSet<RuleLabel> {
         let mut eqv_rules = HashSet::new();
-        for rule in self.rule_to_strategy.keys() {
+        for (rule, strategies) in self.rule_to_strategies.iter() {
+            if rule.get_children().len() == 1 && strategies[0].0.is_equivalence() {
+                continue;
+            }
             let eqv_rule = RuleLabel::new(
                 self.equiv_db.find(*rule.get_parent()),
                 rule.get_children()
@@ -89,40 +499,62 @@ impl<S: Strategy> SimpleRuleDB<S> {
     }
 }
 
+impl<S: Strategy> Default for SimpleRuleDB<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<S: Strategy> RuleDB<S> for SimpleRuleDB<S> {
     fn add(&mut self, start: usize, ends: Vec<usize>, rule: Rule<S>) {
-        if ends.len() == 1 && rule.is_equivalence() {
-            self.equiv_db.union(start, ends[0]);
+        let mut shifted_ends: Vec<_> = ends.iter().cloned().zip(rule.get_shifts()).collect();
+        shifted_ends.sort();
+        let shifts = shifted_ends.iter().map(|&(_, shift)| shift).collect();
+        let label = RuleLabel::new(start, ends);
+        if label.get_children().len() == 1 && rule.is_equivalence() {
+            let child = label.get_children()[0];
+            let eqv_labels = [self.equiv_db.find(start), self.equiv_db.find(child)];
+            self.equiv_db.union(start, child);
+            let kept = self.equiv_db.find(start);
+            let removed = if kept == eqv_labels[0] {
+                eqv_labels[1]
+            } else {
+                eqv_labels[0]
+            };
+            self.productive.merge(kept, removed);
+            // The equivalence can shorten the paths between classes that were
+            // already equivalent, without changing the productive rules.
+            self.smallest = None;
+        } else if !self.rule_to_strategies.contains_key(&label) {
+            let eqv_rule = self.equiv_db.rule_up_to_equivalence(&label);
+            let shifted_children = shifted_ends
+                .iter()
+                .map(|&(c, shift)| (self.equiv_db.find(c), shift))
+                .collect();
+            self.productive
+                .add_rule(label.clone(), eqv_rule, shifted_children);
         }
-        self.rule_to_strategy
-            .insert(RuleLabel::new(start, ends), rule.get_strategy());
+        self.rule_to_strategies
+            .entry(label)
+            .or_default()
+            .push((rule.get_strategy(), shifts));
     }
 
     fn get_specification(
         &mut self,
         root: usize,
         classdb: &classdb::ClassDB<S::ClassType>,
-    ) -> Result<CombinatorialSpecification<S>, SpecificationNotFoundError> {
-        let eqv_specification_rules = self.find_specification(root)?;
-        let specification_rules = self.eqv_specification_to_specification(eqv_specification_rules);
-        let actual_rules: Vec<Rule<S>> = specification_rules
-            .into_iter()
-            .map(|rule| {
-                let parent = classdb
-                    .get_class_from_label(*rule.get_parent())
-                    .unwrap()
-                    .clone();
-                let strategy = self.rule_to_strategy.get(&rule).unwrap().clone();
-                Rule::new(parent, strategy)
-            })
-            .collect();
-        Ok(CombinatorialSpecification {
-            rules: actual_rules,
-            root: classdb.get_class_from_label(root).unwrap().clone(),
-        })
+    ) -> Result<CombinatorialSpecification<S>, SearchError> {
+        let specification_rules = self.find_specification(root)?;
+        Ok(self.to_specification(root, specification_rules, classdb)?)
     }
 }
 
+/// Keep the rules whose children all have a rule that is kept.
+///
+/// This is the productive universe computed from scratch, which
+/// `ProductiveClasses` maintains incrementally.
+#[cfg(test)]
 fn prune(rules: HashSet<RuleLabel>) -> HashMap<usize, Vec<RuleLabel>> {
     let mut rules_by_parent = rules.into_iter().fold(HashMap::new(), |mut map, rule| {
         map.entry(*rule.get_parent())
@@ -143,17 +575,23 @@ fn prune(rules: HashSet<RuleLabel>) -> HashMap<usize, Vec<RuleLabel>> {
         }
         rules_by_parent.retain(|k, _| keys.contains(k));
     }
+    for rules_for_parent in rules_by_parent.values_mut() {
+        rules_for_parent.sort();
+    }
     rules_by_parent
 }
 
-fn random_proof_tree(
+/// Pick one rule for each class reachable from the root, at random if a random
+/// number generator is given and the one with the lowest label otherwise. The rules
+/// of each parent must be sorted.
+fn proof_tree(
     rules_by_parent: &HashMap<usize, Vec<RuleLabel>>,
     root: usize,
+    mut rng: Option<&mut dyn RngCore>,
 ) -> Result<Vec<RuleLabel>, SpecificationNotFoundError> {
     let mut seen = HashSet::new();
     let mut queue = VecDeque::new();
     let mut proof_tree_rules: Vec<RuleLabel> = vec![];
-    let mut rng = thread_rng();
     queue.push_back(root);
     while let Some(parent) = queue.pop_front() {
         if seen.contains(&parent) {
@@ -164,9 +602,11 @@ fn random_proof_tree(
         let rules_for_parent = rules_by_parent
             .get(&parent)
             .ok_or(SpecificationNotFoundError {})?;
-        let chosen_rule_for_parent = rules_for_parent
-            .choose(&mut rng)
-            .ok_or(SpecificationNotFoundError {})?;
+        let chosen_rule_for_parent = match rng.as_mut() {
+            Some(rng) => rules_for_parent.choose(rng),
+            None => rules_for_parent.first(),
+        }
+        .ok_or(SpecificationNotFoundError {})?;
         queue.extend(chosen_rule_for_parent.get_children().iter());
         proof_tree_rules.push(chosen_rule_for_parent.clone());
     }
@@ -176,6 +616,13 @@ fn random_proof_tree(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pack::StrategyPack;
+    use crate::searcher::ruledb::cross_check::{
+        is_valid_proof_tree, label_classdb, random_universe, LabelRule, LabelStrategy,
+        UniverseConfig,
+    };
+    use crate::word::{AvoidingWithPrefix, WordStrategy};
+    use crate::CombinatorialSpecificationSearcher;
 
     #[test]
     fn prune_verification_rule_test() {
@@ -205,4 +652,443 @@ mod tests {
         assert_eq!(rules.len(), 2);
         assert!(!rules.contains_key(&0));
     }
+
+    fn proof_tree_rules() -> HashMap<usize, Vec<RuleLabel>> {
+        let mut rules = HashSet::new();
+        for parent in 0..10 {
+            rules.insert(RuleLabel::new(parent, vec![]));
+            for child in 0..10 {
+                rules.insert(RuleLabel::new(parent, vec![child]));
+            }
+        }
+        prune(rules)
+    }
+
+    #[test]
+    fn proof_tree_lowest_label_test() {
+        let rules = proof_tree_rules();
+        let tree = proof_tree(&rules, 3, None).unwrap();
+        assert_eq!(tree, vec![RuleLabel::new(3, vec![])]);
+    }
+
+    #[test]
+    fn seeded_proof_tree_test() {
+        let trees: Vec<_> = (0..5)
+            .map(|_| {
+                let mut rng = StdRng::seed_from_u64(42);
+                proof_tree(&proof_tree_rules(), 0, Some(&mut rng)).unwrap()
+            })
+            .collect();
+        assert!(trees.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    /// Search a specification of the words avoiding ababa and babb and return the
+    /// parent and strategy of its rules.
+    fn word_specification(ruledb: SimpleRuleDB<WordStrategy>) -> Vec<(String, String)> {
+        let patterns = vec![String::from("ababa"), String::from("babb")];
+        let start_class = AvoidingWithPrefix::new(String::new(), patterns, vec!['a', 'b']);
+        let pack = StrategyPack {
+            initials: vec![WordStrategy::RemoveFrontOfPrefix],
+            inferrals: vec![],
+            expansions: vec![vec![WordStrategy::Expansion]],
+            verifications: vec![WordStrategy::Empty, WordStrategy::Atom],
+        };
+        let mut searcher = CombinatorialSpecificationSearcher::new(start_class, pack, ruledb);
+        let spec = searcher.auto_search().unwrap();
+        spec.rules
+            .into_iter()
+            .map(|rule| {
+                let parent = serde_json::to_string(rule.get_parent()).unwrap();
+                let strategy = serde_json::to_string(&rule.get_strategy()).unwrap();
+                (parent, strategy)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeded_specification_test() {
+        let first = word_specification(SimpleRuleDB::with_seed(7));
+        for _ in 0..3 {
+            assert_eq!(word_specification(SimpleRuleDB::with_seed(7)), first);
+        }
+    }
+
+    #[test]
+    fn smallest_specification_test() {
+        let mut ruledb = SimpleRuleDB::new();
+        ruledb.set_rule_selection(RuleSelection::Smallest);
+        let smallest = word_specification(ruledb);
+        for seed in 0..5 {
+            assert!(smallest.len() <= word_specification(SimpleRuleDB::with_seed(seed)).len());
+        }
+    }
+
+    fn label_rule(parent: usize, children: Vec<usize>, shift: i32) -> LabelRule {
+        LabelRule {
+            parent,
+            shifts: vec![shift; children.len()],
+            children,
+            equivalence: false,
+        }
+    }
+
+    fn label_equivalence(parent: usize, child: usize) -> LabelRule {
+        LabelRule {
+            parent,
+            children: vec![child],
+            shifts: vec![0],
+            equivalence: true,
+        }
+    }
+
+    /// Return the rules of the specification of the `SimpleRuleDB` for the class 0.
+    fn simple_specification(
+        rules: &[LabelRule],
+        rule_selection: RuleSelection,
+        seed: u64,
+    ) -> Option<CombinatorialSpecification<LabelStrategy>> {
+        let classdb = label_classdb(rules, 0);
+        let mut simple = SimpleRuleDB::with_seed(seed);
+        simple.set_rule_selection(rule_selection);
+        for rule in rules.iter().flat_map(|r| r.directed_rules()) {
+            simple.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        simple.get_specification(0, &classdb).ok()
+    }
+
+    fn labels(spec: &CombinatorialSpecification<LabelStrategy>) -> Vec<RuleLabel> {
+        let mut labels: Vec<_> = spec
+            .rules
+            .iter()
+            .map(|r| {
+                RuleLabel::new(
+                    r.get_parent().0,
+                    r.get_children().iter().map(|c| c.0).collect(),
+                )
+            })
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn smallest_label_specification_test() {
+        let rules = vec![
+            label_rule(0, vec![1, 2, 3], 1),
+            label_rule(1, vec![], 0),
+            label_rule(2, vec![], 0),
+            label_rule(3, vec![], 0),
+            label_rule(0, vec![4], 1),
+            label_rule(4, vec![6], 1),
+            label_rule(6, vec![2], 1),
+            label_equivalence(0, 5),
+            label_rule(5, vec![1], 1),
+        ];
+        let spec = simple_specification(&rules, RuleSelection::Smallest, 0).unwrap();
+        assert_eq!(
+            labels(&spec),
+            vec![
+                RuleLabel::new(0, vec![5]),
+                RuleLabel::new(1, vec![]),
+                RuleLabel::new(5, vec![1])
+            ]
+        );
+    }
+
+    /// An equivalence chain costs one rule per equivalence.
+    #[test]
+    fn smallest_specification_with_chain_test() {
+        let rules = vec![
+            label_equivalence(0, 5),
+            label_equivalence(5, 6),
+            label_rule(6, vec![], 0),
+            label_rule(0, vec![1, 1], 1),
+            label_rule(1, vec![], 0),
+        ];
+        let spec = simple_specification(&rules, RuleSelection::Smallest, 0).unwrap();
+        assert_eq!(
+            labels(&spec),
+            vec![RuleLabel::new(0, vec![1, 1]), RuleLabel::new(1, vec![])]
+        );
+    }
+
+    /// The smallest specification is kept until a rule or an equivalence makes a
+    /// smaller one.
+    #[test]
+    fn smallest_specification_cache_test() {
+        let mut ruledb = SimpleRuleDB::new();
+        ruledb.set_rule_selection(RuleSelection::Smallest);
+        let add = |ruledb: &mut SimpleRuleDB<_>, rule: LabelRule| {
+            for rule in rule.directed_rules() {
+                ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+            }
+        };
+        add(&mut ruledb, label_equivalence(0, 1));
+        add(&mut ruledb, label_equivalence(1, 2));
+        add(&mut ruledb, label_rule(2, vec![3], 1));
+        assert!(ruledb.find_specification(0).is_err());
+        add(&mut ruledb, label_rule(3, vec![], 0));
+        assert_eq!(ruledb.find_specification(0).unwrap().len(), 4);
+        assert!(ruledb.smallest.is_some());
+        assert_eq!(ruledb.find_specification(0).unwrap().len(), 4);
+        add(&mut ruledb, label_equivalence(0, 2));
+        assert_eq!(
+            ruledb.find_specification(0).unwrap(),
+            vec![
+                RuleLabel::new(0, vec![2]),
+                RuleLabel::new(2, vec![3]),
+                RuleLabel::new(3, vec![])
+            ]
+        );
+        add(&mut ruledb, label_rule(0, vec![], 0));
+        assert_eq!(
+            ruledb.find_specification(0).unwrap(),
+            vec![RuleLabel::new(0, vec![])]
+        );
+    }
+
+    #[test]
+    fn smallest_specification_random_test() {
+        let config = UniverseConfig {
+            equivalence_probability: 0.3,
+            ..UniverseConfig::new()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let rules = random_universe(&mut rng, &config);
+            let smallest = match simple_specification(&rules, RuleSelection::Smallest, 0) {
+                Some(spec) => spec,
+                None => {
+                    assert!(simple_specification(&rules, RuleSelection::Random, 0).is_none());
+                    continue;
+                }
+            };
+            assert!(is_valid_proof_tree(&smallest, &rules, 0));
+            for seed in 0..10 {
+                let spec = simple_specification(&rules, RuleSelection::Random, seed).unwrap();
+                assert!(smallest.rules.len() <= spec.rules.len());
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_prune_test() {
+        let config = UniverseConfig {
+            num_rules: 14,
+            min_shift: 0,
+            equivalence_probability: 0.3,
+            ..UniverseConfig::new()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..300 {
+            let mut rules: Vec<_> = random_universe(&mut rng, &config)
+                .iter()
+                .flat_map(|r| r.directed_rules())
+                .collect();
+            rules.shuffle(&mut rng);
+            let mut ruledb = SimpleRuleDB::with_seed(0);
+            for rule in rules {
+                ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+                let expected = prune(ruledb.rule_up_to_equivalence());
+                assert_eq!(ruledb.productive.productive_rules(), expected);
+            }
+            let rules: Vec<_> = ruledb.rule_to_strategies.keys().cloned().collect();
+            for rule in rules {
+                let eqv_rule = ruledb.equiv_db.rule_up_to_equivalence(&rule);
+                let indexed = ruledb
+                    .productive
+                    .rules_with_label(&eqv_rule)
+                    .any(|(r, _)| *r == rule);
+                let is_equivalence = ruledb.rule_to_strategies[&rule][0].0.is_equivalence();
+                assert_eq!(indexed, !is_equivalence);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_shift_cycle_test() {
+        let mut ruledb = SimpleRuleDB::with_seed(0);
+        for rule in [
+            label_rule(0, vec![1], 0),
+            label_rule(1, vec![0], 0),
+            label_rule(2, vec![], 0),
+        ] {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        assert!(ruledb.productive.is_productive(0));
+        assert!(ruledb.find_specification(0).is_err());
+        assert_eq!(ruledb.count_specifications(0), Some(0));
+        let rule = label_rule(1, vec![2, 0], 1);
+        ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        for rule_selection in [
+            RuleSelection::Random,
+            RuleSelection::LowestLabel,
+            RuleSelection::Smallest,
+        ] {
+            ruledb.set_rule_selection(rule_selection);
+            assert_eq!(
+                ruledb.find_specification(0).unwrap(),
+                vec![
+                    RuleLabel::new(0, vec![1]),
+                    RuleLabel::new(1, vec![0, 2]),
+                    RuleLabel::new(2, vec![])
+                ]
+            );
+        }
+        assert_eq!(ruledb.count_specifications(0), Some(1));
+    }
+
+    /// The well-founded rules are only computed again once the productive rules
+    /// change.
+    #[test]
+    fn well_founded_cache_test() {
+        let mut ruledb = SimpleRuleDB::with_seed(0);
+        for rule in [label_rule(0, vec![1], 1), label_rule(1, vec![], 0)] {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        let well_founded = ruledb.well_founded_rules(0);
+        assert!(ruledb.find_specification(0).is_ok());
+        assert!(Rc::ptr_eq(&well_founded, &ruledb.well_founded_rules(0)));
+        let rule = label_rule(0, vec![2], 1);
+        ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        assert!(Rc::ptr_eq(&well_founded, &ruledb.well_founded_rules(0)));
+        let rule = label_rule(2, vec![], 0);
+        ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        let well_founded = ruledb.well_founded_rules(0);
+        assert_eq!(well_founded.0[&0].len(), 2);
+        assert_eq!(well_founded.1.len(), 3);
+    }
+
+    #[test]
+    fn strategy_preference_test() {
+        let mut classdb = classdb::ClassDB::new();
+        let class = AvoidingWithPrefix::new(String::new(), vec![String::from("a")], vec!['a']);
+        let label = classdb.get_label_from_class_or_add(&class).unwrap();
+        let mut ruledb = SimpleRuleDB::with_seed(0);
+        ruledb.add(label, vec![], Rule::new(class.clone(), WordStrategy::Empty));
+        ruledb.add(label, vec![], Rule::new(class, WordStrategy::Atom));
+        assert_eq!(
+            ruledb
+                .get_strategies(&RuleLabel::new(label, vec![]))
+                .count(),
+            2
+        );
+        let mut spec = ruledb.get_specification(label, &classdb).unwrap();
+        assert!(matches!(
+            spec.rules.remove(0).get_strategy(),
+            WordStrategy::Empty
+        ));
+        ruledb.set_strategy_preference(|s| match s {
+            WordStrategy::Atom => 0,
+            _ => 1,
+        });
+        let mut spec = ruledb.get_specification(label, &classdb).unwrap();
+        assert!(matches!(
+            spec.rules.remove(0).get_strategy(),
+            WordStrategy::Atom
+        ));
+    }
+
+    /// A strategy that shifts a child less than the first strategy of the rule can
+    /// not replace it, since the specification may need the shift.
+    #[test]
+    fn strategy_preference_shifts_test() {
+        let mut classdb = classdb::ClassDB::new();
+        let rules = [
+            label_rule(0, vec![0, 1], 1),
+            label_rule(0, vec![0, 1], 0),
+            label_rule(0, vec![0, 1], 2),
+            label_rule(1, vec![], 0),
+        ];
+        for rule in rules.iter() {
+            classdb
+                .get_label_from_class_or_add(rule.to_rule().get_parent())
+                .unwrap();
+        }
+        let mut ruledb = SimpleRuleDB::with_seed(0);
+        for rule in rules.iter() {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        let shifts = |ruledb: &mut SimpleRuleDB<_>| {
+            let spec = ruledb.get_specification(0, &classdb).unwrap();
+            spec.rules
+                .iter()
+                .find(|r| !r.get_children().is_empty())
+                .unwrap()
+                .get_shifts()
+        };
+        assert_eq!(shifts(&mut ruledb), vec![1, 1]);
+        let parent = rules[0].to_rule().get_parent().clone();
+        let first_shift =
+            move |s: &LabelStrategy| s.shifts(&parent).first().map_or(0, |&s| s as u64);
+        ruledb.set_strategy_preference(first_shift.clone());
+        assert_eq!(shifts(&mut ruledb), vec![1, 1]);
+        ruledb.set_strategy_preference(move |s| u64::MAX - first_shift(s));
+        assert_eq!(shifts(&mut ruledb), vec![2, 2]);
+    }
+
+    /// The specifications pick one rule for each equivalence class, so a cycle
+    /// gives a single specification.
+    #[test]
+    fn count_specifications_test() {
+        let mut ruledb = SimpleRuleDB::new();
+        assert_eq!(ruledb.count_specifications(0), Some(0));
+        let mut rules = vec![
+            label_rule(0, vec![1], 1),
+            label_rule(1, vec![], 0),
+            label_equivalence(0, 2),
+            label_rule(2, vec![], 0),
+        ];
+        for rule in rules.iter().flat_map(|r| r.directed_rules()) {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        let classdb = label_classdb(&rules, 0);
+        assert_eq!(ruledb.count_specifications(2), Some(2));
+        let specs: Vec<_> = ruledb
+            .iter_specifications(0, &classdb, 10)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_ne!(labels(&specs[0]), labels(&specs[1]));
+        assert!(specs
+            .iter()
+            .all(|spec| is_valid_proof_tree(spec, &rules, 0)));
+        assert_eq!(ruledb.iter_specifications(0, &classdb, 1).count(), 1);
+        rules.push(label_rule(1, vec![0], 1));
+        let rule = rules.last().unwrap();
+        ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        assert_eq!(ruledb.count_specifications(0), Some(3));
+        assert_eq!(ruledb.iter_specifications(0, &classdb, 10).count(), 3);
+    }
+
+    /// A rule that does not shift its child can be used when the child has a
+    /// specification of its own, even if the parent has another one.
+    #[test]
+    fn count_unshifted_rule_test() {
+        let rules = vec![
+            label_rule(0, vec![], 0),
+            label_rule(0, vec![1], 0),
+            label_rule(1, vec![], 0),
+        ];
+        let mut ruledb = SimpleRuleDB::new();
+        for rule in rules.iter() {
+            ruledb.add(rule.parent, rule.children.clone(), rule.to_rule());
+        }
+        let classdb = label_classdb(&rules, 0);
+        assert_eq!(ruledb.count_specifications(0), Some(2));
+        let specs: Vec<_> = ruledb
+            .iter_specifications(0, &classdb, 10)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            specs.iter().map(labels).collect::<Vec<_>>(),
+            vec![
+                vec![RuleLabel::new(0, vec![])],
+                vec![RuleLabel::new(0, vec![1]), RuleLabel::new(1, vec![])]
+            ]
+        );
+        assert!(specs
+            .iter()
+            .all(|spec| is_valid_proof_tree(spec, &rules, 0)));
+    }
 }
@@ -0,0 +1,382 @@
+use crate::searcher::ruledb::RuleLabel;
+use std::collections::{HashMap, HashSet};
+
+/// A rule in term of equivalence labels with the number of its distinct children
+/// that are not productive yet, and the rule it comes from with the size shift of
+/// each child in term of equivalence labels.
+#[derive(Debug)]
+struct CountedRule {
+    label: RuleLabel,
+    unproductive_children: usize,
+    rule: RuleLabel,
+    shifted_children: Vec<(usize, i32)>,
+}
+
+/// Incremental version of the pruning of the rules.
+///
+/// A class is productive if it has a rule whose children are all productive, and
+/// the productive classes are the largest set with that property. Adding a rule or
+/// merging two classes can only make more classes productive, so the set is
+/// updated with a worklist of counters, and cycles that become productive are found
+/// by pruning the unproductive ancestors of the classes touched by the change.
+///
+/// The rules are also indexed by their label in term of equivalence labels, so
+/// that the actual rules behind a rule of a specification are found directly.
+#[derive(Debug)]
+pub struct ProductiveClasses {
+    rules: Vec<CountedRule>,
+    rules_by_label: HashMap<RuleLabel, Vec<usize>>,
+    rules_by_parent: HashMap<usize, HashSet<usize>>,
+    rules_by_child: HashMap<usize, HashSet<usize>>,
+    productive: HashSet<usize>,
+    /// Incremented whenever the productive rules change.
+    generation: u64,
+}
+
+impl ProductiveClasses {
+    pub fn new() -> Self {
+        Self {
+            rules: vec![],
+            rules_by_label: HashMap::new(),
+            rules_by_parent: HashMap::new(),
+            rules_by_child: HashMap::new(),
+            productive: HashSet::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn is_productive(&self, label: usize) -> bool {
+        self.productive.contains(&label)
+    }
+
+    /// Return a number that changes whenever the productive rules change, so that
+    /// what is computed from them can be kept until then.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Add a rule given by its label, its label in term of equivalence labels and
+    /// the size shift of each child in term of equivalence labels.
+    pub fn add_rule(
+        &mut self,
+        rule: RuleLabel,
+        label: RuleLabel,
+        shifted_children: Vec<(usize, i32)>,
+    ) {
+        let parent = *label.get_parent();
+        let idx = self.rules.len();
+        let unproductive_children = self.count_unproductive_children(&label);
+        self.rules_by_label
+            .entry(label.clone())
+            .or_default()
+            .push(idx);
+        self.rules_by_parent.entry(parent).or_default().insert(idx);
+        for &child in label.get_children() {
+            self.rules_by_child.entry(child).or_default().insert(idx);
+        }
+        let dead_end = label
+            .get_children()
+            .iter()
+            .any(|c| !self.productive.contains(c) && !self.rules_by_parent.contains_key(c));
+        self.rules.push(CountedRule {
+            label,
+            unproductive_children,
+            rule,
+            shifted_children,
+        });
+        if unproductive_children == 0 {
+            self.generation += 1;
+        }
+        if self.productive.contains(&parent) || dead_end {
+            return;
+        }
+        let seeds = if unproductive_children == 0 {
+            self.set_productive(parent)
+        } else {
+            vec![parent]
+        };
+        self.find_productive_cycles(seeds);
+    }
+
+    /// Merge the class `removed` into the class `kept` after they were found to be
+    /// equivalent.
+    pub fn merge(&mut self, kept: usize, removed: usize) {
+        if kept == removed {
+            return;
+        }
+        self.generation += 1;
+        let as_parent = self.rules_by_parent.remove(&removed).unwrap_or_default();
+        let as_child = self.rules_by_child.remove(&removed).unwrap_or_default();
+        let relabel = |label: usize| if label == removed { kept } else { label };
+        let changed: HashSet<_> = as_parent.union(&as_child).cloned().collect();
+        for &idx in changed.iter() {
+            let old_label = &self.rules[idx].label;
+            let new_label = RuleLabel::new(
+                relabel(*old_label.get_parent()),
+                old_label
+                    .get_children()
+                    .iter()
+                    .map(|&c| relabel(c))
+                    .collect(),
+            );
+            let same_label = self.rules_by_label.get_mut(old_label).unwrap();
+            same_label.retain(|&i| i != idx);
+            if same_label.is_empty() {
+                self.rules_by_label.remove(old_label);
+            }
+            self.rules_by_label
+                .entry(new_label.clone())
+                .or_default()
+                .push(idx);
+            self.rules[idx].label = new_label;
+            for (child, _) in self.rules[idx].shifted_children.iter_mut() {
+                *child = relabel(*child);
+            }
+        }
+        self.rules_by_parent
+            .entry(kept)
+            .or_default()
+            .extend(as_parent.iter());
+        self.rules_by_child
+            .entry(kept)
+            .or_default()
+            .extend(as_child.iter());
+        let removed_was_productive = self.productive.remove(&removed);
+        for &idx in changed.iter() {
+            self.rules[idx].unproductive_children =
+                self.count_unproductive_children(&self.rules[idx].label);
+        }
+        let mut seeds = vec![kept];
+        if removed_was_productive {
+            seeds.extend(self.set_productive(kept));
+        }
+        seeds.extend(
+            as_child
+                .iter()
+                .map(|&idx| *self.rules[idx].label.get_parent()),
+        );
+        self.find_productive_cycles(seeds);
+    }
+
+    /// Return the rules whose children are all productive, by parent.
+    pub fn productive_rules(&self) -> HashMap<usize, Vec<RuleLabel>> {
+        let mut rules_by_parent: HashMap<usize, Vec<RuleLabel>> = HashMap::new();
+        for rule in self.rules.iter() {
+            if rule.unproductive_children == 0 {
+                rules_by_parent
+                    .entry(*rule.label.get_parent())
+                    .or_default()
+                    .push(rule.label.clone());
+            }
+        }
+        for rules_for_parent in rules_by_parent.values_mut() {
+            rules_for_parent.sort();
+            rules_for_parent.dedup();
+        }
+        rules_by_parent
+    }
+
+    /// Return the rules whose label in term of equivalence labels is the given one,
+    /// with the shift of each child in term of equivalence labels.
+    pub fn rules_with_label<'a>(
+        &'a self,
+        label: &RuleLabel,
+    ) -> impl Iterator<Item = (&'a RuleLabel, &'a [(usize, i32)])> + 'a {
+        self.rules_by_label
+            .get(label)
+            .into_iter()
+            .flatten()
+            .map(|&idx| {
+                let rule = &self.rules[idx];
+                (&rule.rule, rule.shifted_children.as_slice())
+            })
+    }
+
+    fn count_unproductive_children(&self, label: &RuleLabel) -> usize {
+        let children: HashSet<_> = label.get_children().iter().collect();
+        children
+            .into_iter()
+            .filter(|c| !self.productive.contains(c))
+            .count()
+    }
+
+    /// Mark the class as productive and propagate to the rules that use it.
+    ///
+    /// Return the unproductive parents of the rules that have new productive
+    /// children but are not ready yet.
+    fn set_productive(&mut self, label: usize) -> Vec<usize> {
+        let mut waiting_parents = vec![];
+        let mut worklist = vec![label];
+        while let Some(label) = worklist.pop() {
+            if !self.productive.insert(label) {
+                continue;
+            }
+            for &idx in self.rules_by_child.get(&label).into_iter().flatten() {
+                let rule = &mut self.rules[idx];
+                rule.unproductive_children -= 1;
+                if rule.unproductive_children == 0 {
+                    self.generation += 1;
+                    worklist.push(*rule.label.get_parent());
+                } else {
+                    waiting_parents.push(*rule.label.get_parent());
+                }
+            }
+        }
+        waiting_parents
+            .into_iter()
+            .filter(|p| !self.productive.contains(p))
+            .collect()
+    }
+
+    /// Find the classes that became productive through a cycle of rules.
+    ///
+    /// Such a class reaches one of the seeds through unproductive classes, where
+    /// the seeds are the parents of the rules that changed. The unproductive
+    /// ancestors of the seeds are pruned like the whole universe would be, and the
+    /// classes that remain are productive.
+    fn find_productive_cycles(&mut self, seeds: Vec<usize>) {
+        let mut candidates = HashSet::new();
+        let mut stack: Vec<_> = seeds
+            .into_iter()
+            .filter(|s| !self.productive.contains(s))
+            .collect();
+        while let Some(label) = stack.pop() {
+            if !candidates.insert(label) {
+                continue;
+            }
+            for &idx in self.rules_by_child.get(&label).into_iter().flatten() {
+                let parent = *self.rules[idx].label.get_parent();
+                if !self.productive.contains(&parent) && !candidates.contains(&parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let unsupported: Vec<_> = candidates
+                .iter()
+                .filter(|label| {
+                    !self
+                        .rules_by_parent
+                        .get(label)
+                        .into_iter()
+                        .flatten()
+                        .any(|&idx| {
+                            self.rules[idx]
+                                .label
+                                .get_children()
+                                .iter()
+                                .all(|c| self.productive.contains(c) || candidates.contains(c))
+                        })
+                })
+                .cloned()
+                .collect();
+            for label in unsupported {
+                candidates.remove(&label);
+                changed = true;
+            }
+        }
+        for label in candidates {
+            self.set_productive(label);
+        }
+    }
+}
+
+impl Default for ProductiveClasses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Add a rule of classes that are not equivalent to any other class yet.
+    fn add(productive: &mut ProductiveClasses, parent: usize, children: Vec<usize>) {
+        let shifted_children = children.iter().map(|&c| (c, 1)).collect();
+        let label = RuleLabel::new(parent, children);
+        productive.add_rule(label.clone(), label, shifted_children);
+    }
+
+    #[test]
+    fn productive_tree_test() {
+        let mut productive = ProductiveClasses::new();
+        add(&mut productive, 0, vec![1, 2]);
+        add(&mut productive, 1, vec![]);
+        assert!(productive.is_productive(1));
+        assert!(!productive.is_productive(0));
+        add(&mut productive, 2, vec![1]);
+        assert!(productive.is_productive(2));
+        assert!(productive.is_productive(0));
+    }
+
+    #[test]
+    fn productive_cycle_test() {
+        let mut productive = ProductiveClasses::new();
+        add(&mut productive, 0, vec![1, 2]);
+        add(&mut productive, 2, vec![3, 0]);
+        add(&mut productive, 1, vec![]);
+        assert!(!productive.is_productive(0));
+        add(&mut productive, 3, vec![]);
+        assert!(productive.is_productive(0));
+        assert!(productive.is_productive(2));
+        assert_eq!(productive.productive_rules().len(), 4);
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut productive = ProductiveClasses::new();
+        add(&mut productive, 0, vec![1]);
+        add(&mut productive, 2, vec![0]);
+        add(&mut productive, 3, vec![]);
+        assert!(!productive.is_productive(0));
+        productive.merge(3, 1);
+        assert!(productive.is_productive(0));
+        assert!(productive.is_productive(2));
+        assert_eq!(
+            productive.productive_rules()[&0],
+            vec![RuleLabel::new(0, vec![3])]
+        );
+        let rules: Vec<_> = productive
+            .rules_with_label(&RuleLabel::new(0, vec![3]))
+            .map(|(rule, _)| rule)
+            .collect();
+        assert_eq!(rules, vec![&RuleLabel::new(0, vec![1])]);
+        assert_eq!(
+            productive
+                .rules_with_label(&RuleLabel::new(0, vec![1]))
+                .count(),
+            0
+        );
+    }
+
+    /// Only the rules whose children are all productive change the generation.
+    #[test]
+    fn generation_test() {
+        let mut productive = ProductiveClasses::new();
+        add(&mut productive, 0, vec![1]);
+        let generation = productive.generation();
+        add(&mut productive, 2, vec![0]);
+        assert_eq!(productive.generation(), generation);
+        add(&mut productive, 1, vec![]);
+        let generation = productive.generation();
+        assert!(generation > 0);
+        add(&mut productive, 3, vec![4]);
+        assert_eq!(productive.generation(), generation);
+        productive.merge(1, 4);
+        assert!(productive.generation() > generation);
+    }
+
+    #[test]
+    fn merge_cycle_test() {
+        let mut productive = ProductiveClasses::new();
+        add(&mut productive, 0, vec![1]);
+        add(&mut productive, 2, vec![0]);
+        assert!(!productive.is_productive(0));
+        productive.merge(2, 1);
+        assert!(productive.is_productive(0));
+        assert!(productive.is_productive(2));
+    }
+}
@@ -0,0 +1,445 @@
+use super::RuleOption;
+use crate::searcher::ruledb::RuleLabel;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Count the proof trees of the root with the given rules, that is the ways to
+/// pick one rule for each class reachable from the root such that the children
+/// that are not shifted never lead back to the parent. The shifts must not be
+/// negative. Return `None` if the count does not fit in a `u128`.
+///
+/// The classes are given a rule one at a time, always the lowest class that is
+/// reached but has no rule yet. The number of ways to complete a choice only
+/// depends on the classes waiting for a rule, on the classes with a rule that
+/// they can still reach, and on the classes waiting for a rule that these reach
+/// without shift, so the counts are memoized on them. This is still exponential
+/// in the worst case.
+pub fn count_proof_trees(options: &HashMap<usize, Vec<RuleOption>>, root: usize) -> Option<u128> {
+    let mut counter = ProofTreeCounter {
+        options,
+        counts: HashMap::new(),
+    };
+    counter.count(&HashMap::new(), BTreeSet::from([root]))
+}
+
+/// The classes waiting for a rule with, for each class with a rule that they
+/// can reach, the classes waiting for a rule that it reaches without shift.
+type CountKey = (Vec<usize>, Vec<(usize, Vec<usize>)>);
+
+/// The memoized counts of the ways to complete a choice of rules.
+struct ProofTreeCounter<'a> {
+    options: &'a HashMap<usize, Vec<RuleOption>>,
+    counts: HashMap<CountKey, u128>,
+}
+
+impl ProofTreeCounter<'_> {
+    /// Count the ways to give a rule to the pending classes and to the classes they
+    /// reach, given the rule chosen for the classes that already have one.
+    fn count(
+        &mut self,
+        chosen: &HashMap<usize, usize>,
+        mut pending: BTreeSet<usize>,
+    ) -> Option<u128> {
+        let label = match pending.first() {
+            Some(&label) => label,
+            None => return Some(1),
+        };
+        let reachable = self.reachable(&pending);
+        let mut reached_chosen: Vec<_> = chosen
+            .keys()
+            .filter(|c| reachable.contains(c))
+            .map(|&c| {
+                let mut exits: Vec<_> = unshifted_descendants(self.options, chosen, c)
+                    .into_iter()
+                    .filter(|d| !chosen.contains_key(d))
+                    .collect();
+                exits.sort();
+                (c, exits)
+            })
+            .collect();
+        reached_chosen.sort();
+        let key = (pending.iter().cloned().collect(), reached_chosen);
+        if let Some(&count) = self.counts.get(&key) {
+            return Some(count);
+        }
+        pending.remove(&label);
+        let mut count = 0u128;
+        let options = self.options;
+        for (idx, option) in options.get(&label).into_iter().flatten().enumerate() {
+            if closes_unshifted_cycle(options, chosen, label, option) {
+                continue;
+            }
+            let mut option_chosen = chosen.clone();
+            option_chosen.insert(label, idx);
+            let mut option_pending = pending.clone();
+            option_pending.extend(
+                option
+                    .1
+                    .iter()
+                    .map(|&(c, _)| c)
+                    .filter(|c| !option_chosen.contains_key(c)),
+            );
+            count = count.checked_add(self.count(&option_chosen, option_pending)?)?;
+        }
+        self.counts.insert(key, count);
+        Some(count)
+    }
+
+    /// Return the classes reachable from the given classes with any rule.
+    fn reachable(&self, labels: &BTreeSet<usize>) -> HashSet<usize> {
+        let mut reached: HashSet<usize> = labels.iter().cloned().collect();
+        let mut to_visit: Vec<usize> = labels.iter().cloned().collect();
+        while let Some(label) = to_visit.pop() {
+            for (_, shifted_children) in self.options.get(&label).into_iter().flatten() {
+                to_visit.extend(
+                    shifted_children
+                        .iter()
+                        .map(|&(c, _)| c)
+                        .filter(|&c| reached.insert(c)),
+                );
+            }
+        }
+        reached
+    }
+}
+
+/// Return the children of the rule that are not shifted.
+fn unshifted_children(option: &RuleOption) -> impl Iterator<Item = usize> + '_ {
+    option
+        .1
+        .iter()
+        .filter(|&&(_, shift)| shift == 0)
+        .map(|&(c, _)| c)
+}
+
+/// Return the classes reached from the class without shift, following the rules
+/// chosen for the classes that have one.
+fn unshifted_descendants(
+    options: &HashMap<usize, Vec<RuleOption>>,
+    chosen: &HashMap<usize, usize>,
+    label: usize,
+) -> HashSet<usize> {
+    let mut reached = HashSet::new();
+    let mut to_visit = vec![label];
+    while let Some(label) = to_visit.pop() {
+        if let Some(&idx) = chosen.get(&label) {
+            to_visit
+                .extend(unshifted_children(&options[&label][idx]).filter(|&c| reached.insert(c)));
+        }
+    }
+    reached
+}
+
+/// Return true if choosing the rule for the class closes a cycle of children that
+/// are not shifted with the rules already chosen.
+fn closes_unshifted_cycle(
+    options: &HashMap<usize, Vec<RuleOption>>,
+    chosen: &HashMap<usize, usize>,
+    label: usize,
+    option: &RuleOption,
+) -> bool {
+    unshifted_children(option)
+        .any(|c| c == label || unshifted_descendants(options, chosen, c).contains(&label))
+}
+
+/// A rule of a proof tree being built, with the classes that were waiting for a
+/// rule before it was chosen.
+struct Choice {
+    label: usize,
+    rule_idx: usize,
+    pending: BTreeSet<usize>,
+}
+
+/// Lazy iterator over the proof trees of a class, each given as the rules it picks
+/// for the reachable classes.
+///
+/// The proof trees are built depth first, giving a rule to the lowest class that
+/// needs one and backtracking to the next rule once a tree is complete, a class
+/// has no rule left or a rule closes a cycle of children that are not shifted.
+/// Only the current choice of rules is kept in memory.
+pub struct ProofTreeIterator {
+    options: HashMap<usize, Vec<RuleOption>>,
+    limit: usize,
+    choices: Vec<Choice>,
+    /// The index of the rule chosen for each class that has one.
+    chosen: HashMap<usize, usize>,
+    /// The next rule to try, if any.
+    next_choice: Option<Choice>,
+}
+
+impl ProofTreeIterator {
+    /// Iterate over at most `limit` proof trees of the root with the given rules.
+    /// The shifts must not be negative.
+    pub fn new(options: HashMap<usize, Vec<RuleOption>>, root: usize, limit: usize) -> Self {
+        Self {
+            options,
+            limit,
+            choices: vec![],
+            chosen: HashMap::new(),
+            next_choice: Some(Choice {
+                label: root,
+                rule_idx: 0,
+                pending: BTreeSet::from([root]),
+            }),
+        }
+    }
+
+    /// Undo the last choice and return the choice of the next rule for its class.
+    fn backtrack(&mut self) -> Option<Choice> {
+        let choice = self.choices.pop()?;
+        self.chosen.remove(&choice.label);
+        Some(Choice {
+            rule_idx: choice.rule_idx + 1,
+            ..choice
+        })
+    }
+}
+
+impl Iterator for ProofTreeIterator {
+    type Item = Vec<RuleLabel>;
+
+    fn next(&mut self) -> Option<Vec<RuleLabel>> {
+        if self.limit == 0 {
+            return None;
+        }
+        while let Some(choice) = self.next_choice.take() {
+            let option = match self
+                .options
+                .get(&choice.label)
+                .and_then(|options| options.get(choice.rule_idx))
+            {
+                Some(option) => option,
+                None => {
+                    self.next_choice = self.backtrack();
+                    continue;
+                }
+            };
+            if closes_unshifted_cycle(&self.options, &self.chosen, choice.label, option) {
+                self.next_choice = Some(Choice {
+                    rule_idx: choice.rule_idx + 1,
+                    ..choice
+                });
+                continue;
+            }
+            self.chosen.insert(choice.label, choice.rule_idx);
+            let mut pending = choice.pending.clone();
+            pending.remove(&choice.label);
+            pending.extend(
+                option
+                    .1
+                    .iter()
+                    .map(|&(c, _)| c)
+                    .filter(|c| !self.chosen.contains_key(c)),
+            );
+            self.choices.push(choice);
+            match pending.first() {
+                Some(&label) => {
+                    self.next_choice = Some(Choice {
+                        label,
+                        rule_idx: 0,
+                        pending,
+                    })
+                }
+                None => {
+                    let proof_tree = self
+                        .choices
+                        .iter()
+                        .map(|c| self.options[&c.label][c.rule_idx].0.clone())
+                        .collect();
+                    self.next_choice = self.backtrack();
+                    self.limit -= 1;
+                    return Some(proof_tree);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::well_founded::{well_founded_ranks, ShiftedRule};
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Return the rules by parent, each child of a rule shifted as given.
+    fn options(rules: &[(usize, Vec<usize>, i32)]) -> HashMap<usize, Vec<RuleOption>> {
+        let mut options: HashMap<usize, Vec<RuleOption>> = HashMap::new();
+        for (parent, children, shift) in rules {
+            let rule = RuleLabel::new(*parent, children.clone());
+            let shifted_children = rule.get_children().iter().map(|&c| (c, *shift)).collect();
+            options
+                .entry(*parent)
+                .or_default()
+                .push((rule, shifted_children));
+        }
+        for options_for_parent in options.values_mut() {
+            options_for_parent.sort();
+            options_for_parent.dedup_by(|(r1, _), (r2, _)| r1 == r2);
+        }
+        options
+    }
+
+    /// Return the rules by parent, every child shifted.
+    fn rules_by_parent(rules: &[(usize, Vec<usize>)]) -> HashMap<usize, Vec<RuleOption>> {
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|(parent, children)| (*parent, children.clone(), 1))
+            .collect();
+        options(&rules)
+    }
+
+    #[test]
+    fn count_dag_test() {
+        let rules = rules_by_parent(&[
+            (0, vec![1, 2]),
+            (0, vec![3]),
+            (1, vec![]),
+            (1, vec![3]),
+            (2, vec![]),
+            (3, vec![]),
+        ]);
+        assert_eq!(count_proof_trees(&rules, 0), Some(3));
+        assert_eq!(count_proof_trees(&rules, 1), Some(2));
+        assert_eq!(count_proof_trees(&rules, 4), Some(0));
+        let trees: Vec<_> = ProofTreeIterator::new(rules, 0, 10).collect();
+        assert_eq!(
+            trees,
+            vec![
+                vec![
+                    RuleLabel::new(0, vec![1, 2]),
+                    RuleLabel::new(1, vec![]),
+                    RuleLabel::new(2, vec![])
+                ],
+                vec![
+                    RuleLabel::new(0, vec![1, 2]),
+                    RuleLabel::new(1, vec![3]),
+                    RuleLabel::new(2, vec![]),
+                    RuleLabel::new(3, vec![])
+                ],
+                vec![RuleLabel::new(0, vec![3]), RuleLabel::new(3, vec![])],
+            ]
+        );
+    }
+
+    /// A class shared by two rules gets a single rule.
+    #[test]
+    fn count_shared_class_test() {
+        let rules = rules_by_parent(&[
+            (0, vec![1, 2]),
+            (1, vec![3]),
+            (2, vec![3]),
+            (3, vec![]),
+            (3, vec![4]),
+            (4, vec![]),
+        ]);
+        assert_eq!(count_proof_trees(&rules, 0), Some(2));
+        assert_eq!(ProofTreeIterator::new(rules, 0, 10).count(), 2);
+    }
+
+    #[test]
+    fn count_cycle_test() {
+        let rules = rules_by_parent(&[(0, vec![0, 1]), (0, vec![2]), (1, vec![]), (2, vec![])]);
+        assert_eq!(count_proof_trees(&rules, 0), Some(2));
+        assert_eq!(count_proof_trees(&rules, 1), Some(1));
+        let trees: Vec<_> = ProofTreeIterator::new(rules.clone(), 0, 10).collect();
+        assert_eq!(
+            trees,
+            vec![
+                vec![RuleLabel::new(0, vec![0, 1]), RuleLabel::new(1, vec![])],
+                vec![RuleLabel::new(0, vec![2]), RuleLabel::new(2, vec![])],
+            ]
+        );
+        assert_eq!(ProofTreeIterator::new(rules, 0, 1).count(), 1);
+    }
+
+    /// A cycle of children that are not shifted is not a proof tree, but its rules
+    /// can still be used apart.
+    #[test]
+    fn unshifted_cycle_test() {
+        let rules = options(&[
+            (0, vec![1], 0),
+            (1, vec![0], 0),
+            (1, vec![2], 0),
+            (2, vec![], 0),
+        ]);
+        assert_eq!(count_proof_trees(&rules, 0), Some(1));
+        assert_eq!(count_proof_trees(&rules, 1), Some(1));
+        let trees: Vec<_> = ProofTreeIterator::new(rules, 0, 10).collect();
+        assert_eq!(
+            trees,
+            vec![vec![
+                RuleLabel::new(0, vec![1]),
+                RuleLabel::new(1, vec![2]),
+                RuleLabel::new(2, vec![])
+            ]]
+        );
+    }
+
+    /// A class without rules gives no proof tree and the other choices are still
+    /// tried.
+    #[test]
+    fn missing_class_test() {
+        let rules = rules_by_parent(&[(0, vec![1]), (0, vec![2]), (2, vec![])]);
+        assert_eq!(count_proof_trees(&rules, 0), Some(1));
+        let trees: Vec<_> = ProofTreeIterator::new(rules, 0, 10).collect();
+        assert_eq!(
+            trees,
+            vec![vec![RuleLabel::new(0, vec![2]), RuleLabel::new(2, vec![])]]
+        );
+    }
+
+    /// Each class of the chain doubles the count, which overflows after 128 classes.
+    #[test]
+    fn count_overflow_test() {
+        let chain = |length: usize| {
+            let rules: Vec<_> = (0..length)
+                .flat_map(|i| [(i, vec![]), (i, vec![i + 1]), (i, vec![i + 1, i + 1])])
+                .chain([(length, vec![])])
+                .collect();
+            rules_by_parent(&rules)
+        };
+        assert_eq!(count_proof_trees(&chain(3), 0), Some(15));
+        assert_eq!(count_proof_trees(&chain(200), 0), None);
+    }
+
+    #[test]
+    fn count_random_test() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let rules: Vec<_> = (0..10)
+                .map(|_| {
+                    let num_children = rng.gen_range(0..3);
+                    (
+                        rng.gen_range(0..6),
+                        (0..num_children).map(|_| rng.gen_range(0..6)).collect(),
+                        rng.gen_range(0..2),
+                    )
+                })
+                .collect();
+            let rules = options(&rules);
+            let trees: Vec<_> = ProofTreeIterator::new(rules.clone(), 0, usize::MAX).collect();
+            let distinct: HashSet<_> = trees.iter().collect();
+            assert_eq!(distinct.len(), trees.len());
+            assert_eq!(count_proof_trees(&rules, 0), Some(trees.len() as u128));
+            // Every choice of rules, kept if it is well-founded.
+            let shifted: HashMap<_, _> = rules.values().flatten().cloned().collect();
+            let unconstrained = rules
+                .values()
+                .flatten()
+                .map(|(rule, _)| (*rule.get_parent(), rule.get_children().to_vec()))
+                .collect::<Vec<_>>();
+            let well_founded: HashSet<_> =
+                ProofTreeIterator::new(rules_by_parent(&unconstrained), 0, usize::MAX)
+                    .filter(|tree| {
+                        let shifted_rules: HashMap<usize, Vec<ShiftedRule>> = tree
+                            .iter()
+                            .map(|rule| (*rule.get_parent(), vec![shifted[rule].as_slice()]))
+                            .collect();
+                        well_founded_ranks(&shifted_rules, 0).contains_key(&0)
+                    })
+                    .collect();
+            assert_eq!(distinct, well_founded.iter().collect());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// The children of a rule in term of equivalence labels with their size shifts.
+pub type ShiftedRule<'a> = &'a [(usize, i32)];
+
+/// Rank the classes reachable from the root that have a well-founded
+/// specification with the given rules.
+///
+/// A specification defines a unique counting sequence when every cycle of its
+/// rules shifts the size, that is when the children that are not shifted never
+/// lead back to the parent. The ranked classes are the largest set of classes
+/// with a rule whose children are all ranked and whose children that are not
+/// shifted have a lower rank. Rules with a negative shift are never used, since
+/// their children can need more terms than the parent.
+pub fn well_founded_ranks(
+    rules_by_parent: &HashMap<usize, Vec<ShiftedRule>>,
+    root: usize,
+) -> HashMap<usize, usize> {
+    let mut alive = HashSet::from([root]);
+    let mut stack = vec![root];
+    while let Some(label) = stack.pop() {
+        for shifted_children in rules_by_parent.get(&label).into_iter().flatten() {
+            stack.extend(
+                shifted_children
+                    .iter()
+                    .map(|&(c, _)| c)
+                    .filter(|&c| alive.insert(c)),
+            );
+        }
+    }
+    loop {
+        let ranks = rank_classes(rules_by_parent, &alive);
+        if ranks.len() == alive.len() {
+            return ranks;
+        }
+        alive = ranks.into_keys().collect();
+    }
+}
+
+/// Return true if the rule can be used for the parent in a specification where
+/// every class uses such a rule.
+pub fn is_well_founded_rule(
+    ranks: &HashMap<usize, usize>,
+    parent: usize,
+    shifted_children: ShiftedRule,
+) -> bool {
+    let parent_rank = match ranks.get(&parent) {
+        Some(&rank) => rank,
+        None => return false,
+    };
+    shifted_children
+        .iter()
+        .all(|(c, shift)| match ranks.get(c) {
+            Some(&rank) => *shift > 0 || (*shift == 0 && rank < parent_rank),
+            None => false,
+        })
+}
+
+/// Rank the alive classes that can be counted assuming the alive classes can.
+///
+/// A class has rank 0 if it has a rule that shifts all its children, and
+/// otherwise one more than the highest rank of the children that a rule does not
+/// shift, for the best rule.
+fn rank_classes(
+    rules_by_parent: &HashMap<usize, Vec<ShiftedRule>>,
+    alive: &HashSet<usize>,
+) -> HashMap<usize, usize> {
+    // The parent of each rule with the number of its unranked children that are
+    // not shifted.
+    let mut waiting: Vec<(usize, usize)> = vec![];
+    let mut rules_by_child: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut heap = BinaryHeap::new();
+    for (&parent, rules) in rules_by_parent.iter().filter(|(p, _)| alive.contains(p)) {
+        for shifted_children in rules {
+            if shifted_children
+                .iter()
+                .any(|(c, shift)| *shift < 0 || !alive.contains(c))
+            {
+                continue;
+            }
+            let unshifted: HashSet<_> = shifted_children
+                .iter()
+                .filter(|(_, shift)| *shift == 0)
+                .map(|&(c, _)| c)
+                .collect();
+            if unshifted.is_empty() {
+                heap.push(Reverse((0, parent)));
+            }
+            for &child in unshifted.iter() {
+                rules_by_child.entry(child).or_default().push(waiting.len());
+            }
+            waiting.push((parent, unshifted.len()));
+        }
+    }
+    // The classes are ranked by increasing rank, so the last child of a rule to be
+    // ranked has the highest rank.
+    let mut ranks = HashMap::new();
+    while let Some(Reverse((rank, label))) = heap.pop() {
+        if ranks.contains_key(&label) {
+            continue;
+        }
+        ranks.insert(label, rank);
+        for &idx in rules_by_child.get(&label).into_iter().flatten() {
+            let (parent, unranked) = &mut waiting[idx];
+            *unranked -= 1;
+            if *unranked == 0 {
+                heap.push(Reverse((rank + 1, *parent)));
+            }
+        }
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranks(rules: &[(usize, Vec<(usize, i32)>)], root: usize) -> HashMap<usize, usize> {
+        let mut rules_by_parent: HashMap<usize, Vec<ShiftedRule>> = HashMap::new();
+        for (parent, shifted_children) in rules {
+            rules_by_parent
+                .entry(*parent)
+                .or_default()
+                .push(shifted_children);
+        }
+        well_founded_ranks(&rules_by_parent, root)
+    }
+
+    #[test]
+    fn zero_shift_cycle_test() {
+        let mut rules = vec![(0, vec![(1, 0)]), (1, vec![(0, 0)]), (2, vec![])];
+        assert!(ranks(&rules, 0).is_empty());
+        rules.push((1, vec![(2, 0), (1, 1)]));
+        assert_eq!(ranks(&rules, 0), HashMap::from([(2, 0), (1, 1), (0, 2)]));
+        let ranks = ranks(&rules, 0);
+        assert!(is_well_founded_rule(&ranks, 0, &[(1, 0)]));
+        assert!(!is_well_founded_rule(&ranks, 1, &[(0, 0)]));
+        assert!(is_well_founded_rule(&ranks, 1, &[(2, 0), (1, 1)]));
+    }
+
+    #[test]
+    fn shifted_cycle_test() {
+        let rules = vec![(0, vec![(1, 1), (2, 0)]), (1, vec![(0, 0)]), (2, vec![])];
+        assert_eq!(ranks(&rules, 0), HashMap::from([(0, 1), (1, 2), (2, 0)]));
+    }
+
+    #[test]
+    fn negative_shift_test() {
+        let rules = vec![(0, vec![(1, -1)]), (1, vec![])];
+        assert_eq!(ranks(&rules, 0), HashMap::from([(1, 0)]));
+    }
+}
@@ -1,8 +1,9 @@
-use crate::combinatorial_class::CombinatorialClass;
+use crate::combinatorial_class::{ClassCodec, CombinatorialClass};
 use crate::pack::{Rule, Strategy, StrategyFactory};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::HashMap;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct AvoidingWithPrefix {
     prefix: String,
     patterns: Vec<String>,
@@ -41,7 +42,166 @@ impl AvoidingWithPrefix {
     }
 }
 
-impl CombinatorialClass for AvoidingWithPrefix {}
+impl CombinatorialClass for AvoidingWithPrefix {
+    fn codec() -> Option<Box<dyn ClassCodec<Self>>> {
+        Some(Box::new(AvoidingWithPrefixCodec::new()))
+    }
+
+    fn is_empty(&self) -> Option<bool> {
+        Some(self.is_emtpy())
+    }
+}
+
+/// Codec keeping the alphabets and the patterns of the classes once, since a
+/// search usually shares them between all its classes. A class is encoded as the
+/// index of its alphabet and patterns, whether it is just the prefix and its
+/// prefix, with as few bits per letter as the alphabet allows.
+#[derive(Default)]
+pub struct AvoidingWithPrefixCodec {
+    /// The alphabets and the patterns kept, by index.
+    contexts: Vec<(Vec<char>, Vec<String>)>,
+    /// The index of each alphabet and patterns kept.
+    context_index: HashMap<Vec<char>, HashMap<Vec<String>, usize>>,
+}
+
+impl AvoidingWithPrefixCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClassCodec<AvoidingWithPrefix> for AvoidingWithPrefixCodec {
+    fn intern(&mut self, class: &AvoidingWithPrefix) {
+        let by_patterns = self
+            .context_index
+            .entry(class.alphabet.clone())
+            .or_default();
+        if !by_patterns.contains_key(&class.patterns) {
+            by_patterns.insert(class.patterns.clone(), self.contexts.len());
+            self.contexts
+                .push((class.alphabet.clone(), class.patterns.clone()));
+        }
+    }
+
+    fn encode(&self, class: &AvoidingWithPrefix) -> Option<Vec<u8>> {
+        let context = self
+            .context_index
+            .get(&class.alphabet)?
+            .get(&class.patterns)?;
+        let mut bytes = vec![u8::from(class.just_prefix)];
+        compression::write_varint(&mut bytes, *context);
+        let indices = class
+            .prefix
+            .chars()
+            .map(|c| class.alphabet.iter().position(|&l| l == c))
+            .collect::<Option<Vec<_>>>()?;
+        compression::write_word(&mut bytes, &indices, class.alphabet.len());
+        Some(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> AvoidingWithPrefix {
+        let mut reader = compression::Reader::new(bytes);
+        let just_prefix = reader.read_byte() == 1;
+        let (alphabet, patterns) = &self.contexts[reader.read_varint()];
+        let prefix = reader
+            .read_word(alphabet.len())
+            .into_iter()
+            .map(|idx| alphabet[idx])
+            .collect();
+        AvoidingWithPrefix {
+            prefix,
+            patterns: patterns.clone(),
+            alphabet: alphabet.clone(),
+            just_prefix,
+        }
+    }
+}
+
+/// Helpers for the compact encoding of the words.
+mod compression {
+    /// Return the number of bits needed for the index of a letter.
+    fn bits_per_letter(alphabet_size: usize) -> u32 {
+        usize::BITS - alphabet_size.saturating_sub(1).leading_zeros()
+    }
+
+    /// Write the number with 7 bits per byte, the last byte having its high bit
+    /// unset.
+    pub fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            bytes.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+
+    /// Write the length of the word followed by the indices of its letters.
+    pub fn write_word(bytes: &mut Vec<u8>, indices: &[usize], alphabet_size: usize) {
+        write_varint(bytes, indices.len());
+        let bits = bits_per_letter(alphabet_size);
+        let mut buffer = 0u64;
+        let mut buffered = 0;
+        for &idx in indices {
+            buffer |= (idx as u64) << buffered;
+            buffered += bits;
+            while buffered >= 8 {
+                bytes.push(buffer as u8);
+                buffer >>= 8;
+                buffered -= 8;
+            }
+        }
+        if buffered > 0 {
+            bytes.push(buffer as u8);
+        }
+    }
+
+    pub struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        pub fn new(bytes: &'a [u8]) -> Self {
+            Self { bytes, pos: 0 }
+        }
+
+        pub fn read_byte(&mut self) -> u8 {
+            self.pos += 1;
+            self.bytes[self.pos - 1]
+        }
+
+        pub fn read_varint(&mut self) -> usize {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = self.read_byte();
+                value |= ((byte & 0x7f) as usize) << shift;
+                if byte < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+
+        pub fn read_word(&mut self, alphabet_size: usize) -> Vec<usize> {
+            let len = self.read_varint();
+            let bits = bits_per_letter(alphabet_size);
+            let mask = (1u64 << bits) - 1;
+            let mut indices = Vec::with_capacity(len);
+            let mut buffer = 0u64;
+            let mut buffered = 0;
+            for _ in 0..len {
+                while buffered < bits {
+                    buffer |= (self.read_byte() as u64) << buffered;
+                    buffered += 8;
+                }
+                indices.push((buffer & mask) as usize);
+                buffer >>= bits;
+                buffered -= bits;
+            }
+            indices
+        }
+    }
+}
 
 impl Serialize for AvoidingWithPrefix {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -103,6 +263,26 @@ impl Strategy for WordStrategy {
             WordStrategy::Expansion => true,
         }
     }
+
+    fn shifts(&self, comb_class: &Self::ClassType) -> Vec<i32> {
+        match self {
+            WordStrategy::Atom => vec![],
+            WordStrategy::Empty => vec![],
+            WordStrategy::RemoveFrontOfPrefix => {
+                remove_front_of_prefix_strategy::shifts(comb_class)
+            }
+            WordStrategy::Expansion => expansion_strategy::shifts(comb_class),
+        }
+    }
+
+    fn is_reversible(&self) -> bool {
+        match self {
+            WordStrategy::Atom => false,
+            WordStrategy::Empty => false,
+            WordStrategy::RemoveFrontOfPrefix => false,
+            WordStrategy::Expansion => true,
+        }
+    }
 }
 
 impl Serialize for WordStrategy {
@@ -185,23 +365,19 @@ mod remove_front_of_prefix_strategy {
 
     fn removable_prefix_length(word: &AvoidingWithPrefix) -> usize {
         let m = word.patterns.iter().map(|s| s.len()).max().unwrap_or(1);
-        let mut safe = if word.prefix.len() > m {
-            word.prefix.len() - m + 0
+        let safe = if word.prefix.len() > m {
+            word.prefix.len() - m
         } else {
             0
         };
-        for i in safe..word.prefix.len() {
-            let end = &word.prefix[i..];
-            if word
-                .patterns
-                .iter()
-                .any(|patt| end == &patt[..cmp::min(end.len(), patt.len())])
-            {
-                break;
-            }
-            safe = i + 1;
-        }
-        safe
+        (safe..word.prefix.len())
+            .find(|&i| {
+                let end = &word.prefix[i..];
+                word.patterns
+                    .iter()
+                    .any(|patt| end == &patt[..cmp::min(end.len(), patt.len())])
+            })
+            .unwrap_or(word.prefix.len())
     }
 
     pub fn decompose(word: &AvoidingWithPrefix) -> Vec<AvoidingWithPrefix> {
@@ -220,6 +396,17 @@ mod remove_front_of_prefix_strategy {
         );
         vec![start, end]
     }
+
+    /// The objects of each child have at least the length of its prefix, so each
+    /// child is shifted by the length of the prefix of the other child.
+    pub fn shifts(word: &AvoidingWithPrefix) -> Vec<i32> {
+        let safe = removable_prefix_length(word);
+        let end_length = word.prefix.len() - safe;
+        vec![
+            i32::try_from(end_length).expect("Shift does not fit in an i32"),
+            i32::try_from(safe).expect("Shift does not fit in an i32"),
+        ]
+    }
 }
 
 mod expansion_strategy {
@@ -249,4 +436,74 @@ mod expansion_strategy {
         }
         children
     }
+
+    /// Every child contains objects of the same size as the parent's objects.
+    pub fn shifts(word: &AvoidingWithPrefix) -> Vec<i32> {
+        vec![0; word.alphabet.len() + 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_test() {
+        let patterns = vec![String::from("ababa"), String::from("babb")];
+        let alphabet = vec!['a', 'b'];
+        let words = [
+            AvoidingWithPrefix::new(String::new(), patterns.clone(), alphabet.clone()),
+            AvoidingWithPrefix::new_just_prefix(
+                String::from("abbabbbaab"),
+                patterns.clone(),
+                alphabet.clone(),
+            ),
+            AvoidingWithPrefix::new(
+                String::from("cab").repeat(50),
+                vec![String::from("abc")],
+                vec!['a', 'b', 'c', 'é'],
+            ),
+        ];
+        let mut codec = AvoidingWithPrefixCodec::new();
+        assert_eq!(codec.encode(&words[0]), None);
+        for word in words.iter() {
+            codec.intern(word);
+            let bytes = codec.encode(word).unwrap();
+            assert_eq!(codec.decode(&bytes), *word);
+        }
+        assert_eq!(codec.contexts.len(), 2);
+        assert_eq!(codec.encode(&words[1]).unwrap().len(), 5);
+        let other_letter = AvoidingWithPrefix::new(String::from("c"), patterns, alphabet);
+        codec.intern(&other_letter);
+        assert_eq!(codec.encode(&other_letter), None);
+    }
+
+    #[test]
+    fn remove_front_of_prefix_shifts_test() {
+        let word = AvoidingWithPrefix::new(
+            String::from("abab"),
+            vec![String::from("bb")],
+            vec!['a', 'b'],
+        );
+        let strategy = WordStrategy::RemoveFrontOfPrefix;
+        let children = strategy.decompose(&word);
+        assert_eq!(children[0].prefix, "aba");
+        assert_eq!(children[1].prefix, "b");
+        assert_eq!(strategy.shifts(&word), vec![1, 3]);
+    }
+
+    #[test]
+    fn expansion_shifts_test() {
+        let word = AvoidingWithPrefix::new(String::from("a"), vec![], vec!['a', 'b']);
+        assert_eq!(WordStrategy::Expansion.shifts(&word), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn remove_front_of_long_prefix_shifts_test() {
+        let word = AvoidingWithPrefix::new("b".repeat(200), vec!["a".repeat(40)], vec!['a', 'b']);
+        assert_eq!(
+            WordStrategy::RemoveFrontOfPrefix.shifts(&word),
+            vec![0, 200]
+        );
+    }
 }